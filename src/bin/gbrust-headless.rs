@@ -0,0 +1,197 @@
+// Runs a ROM without a window, for test ROM suites and build servers.
+//
+// gbrust-headless -f roms/cpu_instrs.gb --frames 3600 --until-serial "Passed" --screenshot out.png
+// gbrust-headless -f game.gb --movie crash.gbm
+//
+// Exit code: 0 when the stop condition is met (or after --frames when there is no condition),
+// 1 when the frame limit is reached first, 2 on invalid arguments.
+
+use gbrust::emulator::Emulator;
+use gbrust::movie::Movie;
+use gbrust::serial::Console;
+use gbrust::{cpu, image, ppu, rom};
+
+use std::cell::Cell;
+use std::convert::TryFrom;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+use std::rc::Rc;
+
+extern crate getopts;
+use getopts::Options;
+
+enum Condition {
+    Serial(String),
+    Pc(u16),
+    Memory(u16, u8),
+}
+
+fn parse_hex<T: TryFrom<u32>>(s: &str) -> Result<T, String> {
+    let digits = s.trim_start_matches("0x").trim_start_matches('$');
+    u32::from_str_radix(digits, 16)
+        .ok()
+        .and_then(|v| T::try_from(v).ok())
+        .ok_or(format!("invalid hex value {}", s))
+}
+
+fn parse_conditions(matches: &getopts::Matches) -> Result<Vec<Condition>, String> {
+    let mut conditions = vec![];
+
+    if let Some(text) = matches.opt_str("until-serial") {
+        conditions.push(Condition::Serial(text));
+    }
+    if let Some(address) = matches.opt_str("until-pc") {
+        conditions.push(Condition::Pc(parse_hex(&address)?));
+    }
+    if let Some(memory) = matches.opt_str("until-memory") {
+        let mut parts = memory.splitn(2, '=');
+        let address = parse_hex(parts.next().unwrap_or(""))?;
+        let value = parse_hex(parts.next().ok_or("expected ADDR=VALUE")?)?;
+        conditions.push(Condition::Memory(address, value));
+    }
+
+    Ok(conditions)
+}
+
+fn usage(opts: &Options, error: &str) -> ! {
+    eprintln!("{}", error);
+    eprint!("{}", opts.usage("Usage: gbrust-headless -f ROM [options]"));
+    process::exit(2);
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    let mut opts = Options::new();
+    opts.optopt("b", "bootrom-file", "set the bootrom file path", "FILE");
+    opts.reqopt("f", "rom-file", "set the rom file path", "FILE");
+    opts.optopt(
+        "",
+        "frames",
+        "number of frames to run at most (default 600, or the length of the movie)",
+        "N",
+    );
+    opts.optopt(
+        "",
+        "until-serial",
+        "stop when the serial output contains the text",
+        "TEXT",
+    );
+    opts.optopt("", "until-pc", "stop when PC reaches the address", "ADDR");
+    opts.optopt(
+        "",
+        "until-memory",
+        "stop when the byte at the address equals the value (hex)",
+        "ADDR=VALUE",
+    );
+    opts.optopt(
+        "",
+        "screenshot",
+        "write the final framebuffer to a PNG file",
+        "FILE",
+    );
+    opts.optopt(
+        "",
+        "scale",
+        "size multiplier of the screenshot (default 1)",
+        "N",
+    );
+    opts.optopt("", "movie", "play the input of a movie file", "FILE");
+    opts.optopt(
+        "",
+        "patch",
+        "apply an IPS, UPS or BPS patch (default: the patch next to the ROM)",
+        "FILE",
+    );
+    opts.optflag("q", "quiet", "don't print the serial output");
+    let matches = match opts.parse(&args[1..]) {
+        Ok(m) => m,
+        Err(f) => usage(&opts, &f.to_string()),
+    };
+
+    let movie = matches.opt_str("movie").map(|path| {
+        Movie::load(&path)
+            .unwrap_or_else(|e| usage(&opts, &format!("failed to load {}: {}", path, e)))
+    });
+
+    let frames = match matches.opt_str("frames") {
+        Some(frames) => frames
+            .parse::<u64>()
+            .unwrap_or_else(|e| usage(&opts, &format!("invalid frames {}: {}", frames, e))),
+        None => match &movie {
+            Some(movie) => movie.frames.len() as u64,
+            None => 600,
+        },
+    };
+    let scale = match matches.opt_str("scale") {
+        Some(scale) => match scale.parse::<usize>() {
+            Ok(scale) if scale > 0 => scale,
+            _ => usage(&opts, &format!("invalid scale {}", scale)),
+        },
+        None => 1,
+    };
+    let conditions = parse_conditions(&matches).unwrap_or_else(|e| usage(&opts, &e));
+
+    let rom_file = matches.opt_str("f").unwrap();
+    let patch = matches.opt_str("patch");
+    let rom = rom::load_with_patch(&rom_file, patch.as_deref().map(Path::new))
+        .unwrap_or_else(|e| usage(&opts, &format!("failed to load {}: {}", rom_file, e)));
+    let boot_rom = matches.opt_str("b").map(|path| {
+        fs::read(&path).unwrap_or_else(|e| usage(&opts, &format!("failed to load {}: {}", path, e)))
+    });
+    let mut emulator = Emulator::from_rom(boot_rom.as_deref(), &rom)
+        .unwrap_or_else(|e| usage(&opts, &e.to_string()));
+    if let Some(movie) = movie {
+        emulator
+            .play_movie(movie)
+            .unwrap_or_else(|e| usage(&opts, &e));
+    }
+    let cpu = &mut emulator.cpu;
+
+    let console = match matches.opt_present("q") {
+        true => Console::default(),
+        false => Console::printing(),
+    };
+    let output = console.output();
+    cpu.mmu.serial.connect(Box::new(console));
+
+    // PC is caught during the frame, the other conditions are checked at the end of every frame
+    let pc_reached = Rc::new(Cell::new(false));
+    for condition in conditions.iter() {
+        if let Condition::Pc(address) = condition {
+            let pc_reached = Rc::clone(&pc_reached);
+            cpu.on_breakpoint(*address, Box::new(move |_| pc_reached.set(true)));
+        }
+    }
+
+    let met = |cpu: &cpu::Cpu| {
+        conditions.iter().any(|condition| match condition {
+            Condition::Serial(text) => output.borrow().contains(text.as_str()),
+            Condition::Pc(_) => pc_reached.get(),
+            Condition::Memory(address, value) => cpu.mmu.read_byte(*address) == *value,
+        })
+    };
+
+    let mut done = false;
+    for _ in 0..frames {
+        emulator.run_frame();
+        if met(&emulator.cpu) {
+            done = true;
+            break;
+        }
+    }
+
+    if let Some(path) = matches.opt_str("screenshot") {
+        let cpu = &emulator.cpu;
+        let scaled = image::scale(ppu::WIDTH, ppu::HEIGHT, &cpu.mmu.ppu.buffer, scale);
+        image::write_png(&path, ppu::WIDTH * scale, ppu::HEIGHT * scale, &scaled)
+            .unwrap_or_else(|e| panic!("failed to write {}: {}", path, e));
+    }
+
+    if !done && !conditions.is_empty() {
+        eprintln!("stop condition not met after {} frames", frames);
+        process::exit(1);
+    }
+}
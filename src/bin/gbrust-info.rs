@@ -0,0 +1,135 @@
+// Prints the cartridge header of ROM files.
+//
+// gbrust-info roms/*.gb roms/*.zip
+//
+// Exit code: 0 when all the headers are valid, 1 when a file can't be read or has
+// a problem (bad logo, checksum, size...), 2 on invalid arguments.
+
+use gbrust::catridge::header::{global_checksum, header_checksum, RomHeader};
+use gbrust::rom;
+
+use std::env;
+use std::process;
+
+extern crate getopts;
+use getopts::Options;
+
+fn size(bytes: Option<usize>, code: u8) -> String {
+    match bytes {
+        Some(0) => "none".to_string(),
+        Some(bytes) if bytes % 1024 == 0 => format!("{}KB", bytes / 1024),
+        Some(bytes) => format!("{} bytes", bytes),
+        None => format!("unknown ({:02X})", code),
+    }
+}
+
+fn print_header(header: &RomHeader, rom: &[u8]) {
+    println!("title:             {}", header.title);
+    if let Some(code) = &header.manufacturer_code {
+        println!("manufacturer code: {}", code);
+    }
+    let cgb = match header.cgb_flag {
+        0xc0 => "CGB only",
+        0x80 => "CGB enhanced",
+        _ => "no",
+    };
+    println!("CGB:               {} ({:02X})", cgb, header.cgb_flag);
+    println!(
+        "SGB:               {} ({:02X})",
+        if header.is_sgb() { "yes" } else { "no" },
+        header.sgb_flag
+    );
+    println!("licensee:          {}", header.licensee_code());
+    println!(
+        "cartridge type:    {} ({:02X})",
+        header.cartridge_type_name().unwrap_or("unknown"),
+        header.cartridge_type
+    );
+    println!(
+        "ROM size:          {}",
+        size(header.rom_size(), header.rom_size_code)
+    );
+    println!(
+        "RAM size:          {}",
+        size(header.ram_size(), header.ram_size_code)
+    );
+    let destination = match header.destination {
+        0x00 => "Japan",
+        0x01 => "overseas",
+        _ => "unknown",
+    };
+    println!(
+        "destination:       {} ({:02X})",
+        destination, header.destination
+    );
+    println!("version:           {}", header.version);
+    println!(
+        "header checksum:   {:02X} (computed {:02X})",
+        header.header_checksum,
+        header_checksum(rom)
+    );
+    println!(
+        "global checksum:   {:04X} (computed {:04X})",
+        header.global_checksum,
+        global_checksum(rom)
+    );
+}
+
+// Returns false when the file has a problem.
+fn inspect(path: &str, quiet: bool) -> bool {
+    let rom = match rom::load(path) {
+        Ok(rom) => rom,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            return false;
+        }
+    };
+    let header = match RomHeader::parse(&rom) {
+        Ok(header) => header,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            return false;
+        }
+    };
+
+    let problems = header.validate(&rom);
+    if !quiet {
+        println!("{}", path);
+        print_header(&header, &rom);
+        println!();
+    }
+    for problem in problems.iter() {
+        eprintln!("{}: {}", path, problem);
+    }
+
+    problems.is_empty()
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    let mut opts = Options::new();
+    opts.optflag("q", "quiet", "only print the problems");
+    let matches = match opts.parse(&args[1..]) {
+        Ok(m) if !m.free.is_empty() => m,
+        Ok(_) => {
+            eprint!("{}", opts.usage("Usage: gbrust-info [options] ROM..."));
+            process::exit(2);
+        }
+        Err(f) => {
+            eprintln!("{}", f);
+            eprint!("{}", opts.usage("Usage: gbrust-info [options] ROM..."));
+            process::exit(2);
+        }
+    };
+
+    let quiet = matches.opt_present("q");
+    let mut valid = true;
+    for path in matches.free.iter() {
+        valid &= inspect(path, quiet);
+    }
+
+    if !valid {
+        process::exit(1);
+    }
+}
@@ -1,84 +1,160 @@
-use std::fs::File;
-use std::io::Read;
-
+pub mod header;
+mod huc1;
+mod huc3;
 mod mbc1;
+mod mbc6;
+mod mbc7;
+mod mmm01;
 mod no_mbc;
 
-enum CatridgeType {
-    NoMbc,
-    Mbc1,
-}
+use crate::rom;
+use header::RomHeader;
 
-pub struct Catridge {
-    cartridge_type: CatridgeType,
-    rom: Vec<u8>,
-    rom_bank: u8,
+// ref. https://gbdev.io/pandocs/MBCs.html
+
+const ROM_BANK_SIZE: usize = 16 * 1024;
+const RAM_BANK_SIZE: usize = 8 * 1024;
+
+/// The memory bank controller of a cartridge, mapping its ROM to $0000-$7FFF and its RAM
+/// (or other hardware) to $A000-$BFFF.
+pub trait Mapper {
+    fn read(&self, address: u16) -> u8;
+    fn write(&mut self, address: u16, value: u8);
+    /// The whole ROM.
+    fn rom(&self) -> &[u8];
 
-    ram: Vec<u8>,
-    ram_enabled: bool,
-    ram_bank: u8,
+    /// Runs the hardware of the cartridge (RTC...) for `clocks` clocks at normal speed.
+    fn step(&mut self, _clocks: usize) {}
+
+    /// Tilt of the cartridge for accelerometers, -1.0 to 1.0 on each axis (right and down).
+    fn set_tilt(&mut self, _x: f32, _y: f32) {}
+}
+
+// The byte at `address` in `bank` of `bank_size` bytes. Mappers mask the bank numbers by the
+// ROM size of the header, the bank numbers of a file smaller than that still wrap around its
+// size. A file smaller than a bank reads $FF past its end.
+fn banked_byte(data: &[u8], bank: usize, bank_size: usize, address: u16) -> u8 {
+    let banks = (data.len() / bank_size).max(1);
+    let offset = (bank % banks) * bank_size + (address as usize & (bank_size - 1));
+    data.get(offset).copied().unwrap_or(0xff)
+}
 
-    rom_banking: bool,
+pub struct Catridge {
+    mapper: Box<dyn Mapper>,
 }
 
 impl Catridge {
     pub fn new(name: &str) -> Self {
-        let mut file = File::open(name).unwrap();
-        let mut rom = Vec::<u8>::new();
+        Catridge::from_bytes(rom::load(name).unwrap()).unwrap_or_else(|e| panic!("{}", e))
+    }
 
-        file.read_to_end(&mut rom).unwrap();
+    /// Fails when the header is missing, invalid or of an unsupported cartridge.
+    pub fn from_bytes(rom: Vec<u8>) -> Result<Self, String> {
+        // MMM01 multicarts start with the menu at the end of the ROM, the header at the start
+        // is the one of the first game, often a MBC1 one
+        if mmm01::is_mmm01(&rom) {
+            return Ok(Catridge {
+                mapper: Box::new(mmm01::Mmm01::new(rom)),
+            });
+        }
 
         // カートリッジヘッダ
         // https://w.atwiki.jp/gbspec/pages/30.html
-
-        let cartridge_type = match rom[0x147] {
-            0x00 => CatridgeType::NoMbc,
-            0x01 => CatridgeType::Mbc1,
-            _ => panic!("not supported catridge type {:#X}", rom[0x147]),
-        };
+        let header = RomHeader::parse(&rom)?;
 
         // 0149 - RAM サイズ
-        let ram_size = match rom[0x0149] {
-            0 => 0,
-            1 => 2 * 1024,
-            2 => 8 * 1024,
-            3 => 32 * 1024,
-            _ => panic!("invalid ram size {}", rom[0x0149]),
+        let ram_size = header
+            .ram_size()
+            .ok_or(format!("invalid ram size {}", header.ram_size_code))?;
+
+        // 0148 - ROM サイズ
+        let rom_size = header
+            .rom_size()
+            .ok_or(format!("invalid rom size {}", header.rom_size_code))?;
+
+        let mapper: Box<dyn Mapper> = match header.cartridge_type {
+            0x00 => Box::new(no_mbc::NoMbc::new(rom)),
+            0x01..=0x03 => Box::new(mbc1::Mbc1::new(rom, rom_size, ram_size)),
+            0x20 => Box::new(mbc6::Mbc6::new(rom, ram_size)),
+            0x22 => Box::new(mbc7::Mbc7::new(rom)),
+            0xfe => Box::new(huc3::HuC3::new(rom, ram_size)),
+            0xff => Box::new(huc1::HuC1::new(rom, ram_size)),
+            _ => {
+                return Err(format!(
+                    "not supported catridge type {:#X}",
+                    header.cartridge_type
+                ))
+            }
         };
 
-        Catridge {
-            cartridge_type,
-            rom,
-            rom_bank: 1,
-            ram: vec![0; ram_size as usize],
-            ram_enabled: false,
-            ram_bank: 0,
-            rom_banking: false,
-        }
+        Ok(Catridge { mapper })
+    }
+
+    pub fn rom(&self) -> &[u8] {
+        self.mapper.rom()
     }
 
     pub fn read(&self, address: u16) -> u8 {
-        match &self.cartridge_type {
-            CatridgeType::NoMbc => no_mbc::read(self, address),
-            CatridgeType::Mbc1 => mbc1::read(self, address),
-        }
+        self.mapper.read(address)
     }
 
     pub fn write(&mut self, address: u16, value: u8) {
-        match &self.cartridge_type {
-            CatridgeType::NoMbc => no_mbc::write(self, address, value),
-            CatridgeType::Mbc1 => mbc1::write(self, address, value),
-        }
+        self.mapper.write(address, value)
     }
 
-    fn update_rom_bank(&mut self) {
-        // When 00h is written, the MBC translates that to bank 01h also
-        // the same happens for Bank 20h, 40h, and 60h
-        match self.rom_bank {
-            0x00 | 0x20 | 0x40 | 0x60 => {
-                self.rom_bank += 1;
-            }
-            _ => {}
-        }
+    pub fn step(&mut self, clocks: usize) {
+        self.mapper.step(clocks)
+    }
+
+    pub fn set_tilt(&mut self, x: f32, y: f32) {
+        self.mapper.set_tilt(x, y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::header::{header_checksum, LOGO};
+    use super::{Catridge, ROM_BANK_SIZE};
+
+    #[test]
+    fn test_invalid_rom() {
+        assert!(Catridge::from_bytes(vec![0; 0x100]).is_err());
+
+        let mut rom = vec![0; 2 * ROM_BANK_SIZE];
+        rom[0x0147] = 0x0f;
+        assert!(Catridge::from_bytes(rom.clone()).is_err());
+        rom[0x0147] = 0x00;
+        rom[0x0149] = 0x07;
+        assert!(Catridge::from_bytes(rom).is_err());
+    }
+
+    #[test]
+    fn test_no_mbc() {
+        // a file shorter than 32KB
+        let mut rom = vec![0; 0x200];
+        rom[0x01ff] = 0x11;
+        let catridge = Catridge::from_bytes(rom).unwrap();
+        assert_eq!(catridge.read(0x01ff), 0x11);
+        assert_eq!(catridge.read(0x4000), 0xff);
+        assert_eq!(catridge.read(0xa000), 0xff);
+    }
+
+    #[test]
+    fn test_mmm01_header() {
+        // a MBC1 game, with the type of MMM01 at $0147 of the last 32KB
+        let mut rom = vec![0; 16 * ROM_BANK_SIZE];
+        rom[0x0147] = 0x01;
+        rom[0x0148] = 0x03;
+        let menu = 14 * ROM_BANK_SIZE;
+        rom[menu] = 0x42;
+        rom[menu + 0x0147] = 0x0b;
+        let catridge = Catridge::from_bytes(rom.clone()).unwrap();
+        assert_eq!(catridge.read(0x0000), 0x00);
+
+        // a MMM01 menu, the first game is a MBC1 one
+        rom[menu + 0x0104..menu + 0x0134].copy_from_slice(&LOGO);
+        rom[menu + 0x014d] = header_checksum(&rom[menu..]);
+        let catridge = Catridge::from_bytes(rom).unwrap();
+        assert_eq!(catridge.read(0x0000), 0x42);
     }
 }
@@ -0,0 +1,348 @@
+// The cartridge header at $0100-$014F
+// ref. https://gbdev.io/pandocs/The_Cartridge_Header.html
+
+use std::fmt;
+
+// $0104-$0133 - Nintendo Logo
+pub const LOGO: [u8; 0x30] = [
+    0xce, 0xed, 0x66, 0x66, 0xcc, 0x0d, 0x00, 0x0b, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0c, 0x00, 0x0d,
+    0x00, 0x08, 0x11, 0x1f, 0x88, 0x89, 0x00, 0x0e, 0xdc, 0xcc, 0x6e, 0xe6, 0xdd, 0xdd, 0xd9, 0x99,
+    0xbb, 0xbb, 0x67, 0x63, 0x6e, 0x0e, 0xec, 0xcc, 0xdd, 0xdc, 0x99, 0x9f, 0xbb, 0xb9, 0x33, 0x3e,
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RomHeader {
+    pub title: String,
+    /// $013F-$0142, in the title area of some CGB games
+    pub manufacturer_code: Option<String>,
+    /// $0143 - $80: CGB enhanced, $C0: CGB only
+    pub cgb_flag: u8,
+    /// $0146 - $03: SGB functions
+    pub sgb_flag: u8,
+    /// $0144-$0145, used when the old licensee code is $33
+    pub new_licensee_code: String,
+    /// $014B
+    pub old_licensee_code: u8,
+    /// $0147
+    pub cartridge_type: u8,
+    /// $0148
+    pub rom_size_code: u8,
+    /// $0149
+    pub ram_size_code: u8,
+    /// $014A - 0: Japan, 1: overseas
+    pub destination: u8,
+    /// $014C - Mask ROM version number
+    pub version: u8,
+    /// $014D
+    pub header_checksum: u8,
+    /// $014E-$014F, big endian
+    pub global_checksum: u16,
+}
+
+/// What's wrong with a ROM.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Problem {
+    Logo,
+    HeaderChecksum {
+        header: u8,
+        computed: u8,
+    },
+    GlobalChecksum {
+        header: u16,
+        computed: u16,
+    },
+    CartridgeType(u8),
+    RomSize(u8),
+    RamSize(u8),
+    /// The file size differs from the ROM size in the header.
+    FileSize {
+        header: usize,
+        file: usize,
+    },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Problem::Logo => write!(f, "the Nintendo logo doesn't match"),
+            Problem::HeaderChecksum { header, computed } => write!(
+                f,
+                "header checksum is {:02X}, computed {:02X}",
+                header, computed
+            ),
+            Problem::GlobalChecksum { header, computed } => write!(
+                f,
+                "global checksum is {:04X}, computed {:04X}",
+                header, computed
+            ),
+            Problem::CartridgeType(code) => write!(f, "unknown cartridge type {:02X}", code),
+            Problem::RomSize(code) => write!(f, "unknown ROM size {:02X}", code),
+            Problem::RamSize(code) => write!(f, "unknown RAM size {:02X}", code),
+            Problem::FileSize { header, file } => write!(
+                f,
+                "the file is {} bytes, the header says {} bytes",
+                file, header
+            ),
+        }
+    }
+}
+
+/// $014D - computed over $0134-$014C, the boot ROM locks up when it doesn't match.
+pub fn header_checksum(rom: &[u8]) -> u8 {
+    rom[0x0134..=0x014c]
+        .iter()
+        .fold(0u8, |x, b| x.wrapping_sub(*b).wrapping_sub(1))
+}
+
+/// $014E-$014F - the sum of all the ROM bytes but the checksum itself. Not verified by hardware.
+pub fn global_checksum(rom: &[u8]) -> u16 {
+    rom.iter()
+        .enumerate()
+        .filter(|(i, _)| *i != 0x014e && *i != 0x014f)
+        .fold(0u16, |sum, (_, b)| sum.wrapping_add(*b as u16))
+}
+
+fn ascii(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .take_while(|b| **b != 0)
+        .map(|b| match b {
+            0x20..=0x7e => *b as char,
+            _ => '?',
+        })
+        .collect::<String>()
+        .trim_end()
+        .to_string()
+}
+
+impl RomHeader {
+    pub fn parse(rom: &[u8]) -> Result<Self, String> {
+        if rom.len() < 0x0150 {
+            return Err(format!(
+                "{} bytes is too small for a ROM with a header",
+                rom.len()
+            ));
+        }
+
+        let cgb_flag = rom[0x0143];
+        // the title is 16 characters, 15 on CGB, 11 when there's a manufacturer code
+        let code = &rom[0x013f..=0x0142];
+        let manufacturer_code = match cgb_flag & 0x80 > 0
+            && code
+                .iter()
+                .all(|b| b.is_ascii_uppercase() || b.is_ascii_digit())
+        {
+            true => Some(ascii(code)),
+            false => None,
+        };
+        let title = match (&manufacturer_code, cgb_flag & 0x80 > 0) {
+            (Some(_), _) => ascii(&rom[0x0134..=0x013e]),
+            (None, true) => ascii(&rom[0x0134..=0x0142]),
+            (None, false) => ascii(&rom[0x0134..=0x0143]),
+        };
+
+        Ok(RomHeader {
+            title,
+            manufacturer_code,
+            cgb_flag,
+            sgb_flag: rom[0x0146],
+            new_licensee_code: ascii(&rom[0x0144..=0x0145]),
+            old_licensee_code: rom[0x014b],
+            cartridge_type: rom[0x0147],
+            rom_size_code: rom[0x0148],
+            ram_size_code: rom[0x0149],
+            destination: rom[0x014a],
+            version: rom[0x014c],
+            header_checksum: rom[0x014d],
+            global_checksum: (rom[0x014e] as u16) << 8 | rom[0x014f] as u16,
+        })
+    }
+
+    pub fn is_cgb(&self) -> bool {
+        self.cgb_flag & 0x80 > 0
+    }
+
+    pub fn is_cgb_only(&self) -> bool {
+        self.cgb_flag == 0xc0
+    }
+
+    pub fn is_sgb(&self) -> bool {
+        self.sgb_flag == 0x03
+    }
+
+    /// The licensee, in the new format ("01") when the old code is $33.
+    pub fn licensee_code(&self) -> String {
+        match self.old_licensee_code {
+            0x33 => self.new_licensee_code.clone(),
+            code => format!("{:02X}", code),
+        }
+    }
+
+    /// ROM size in bytes, None for an unknown code.
+    pub fn rom_size(&self) -> Option<usize> {
+        match self.rom_size_code {
+            // 32KB << N
+            code @ 0x00..=0x08 => Some((32 * 1024) << code),
+            // sizes only listed in unofficial docs
+            0x52 => Some(72 * 16 * 1024),
+            0x53 => Some(80 * 16 * 1024),
+            0x54 => Some(96 * 16 * 1024),
+            _ => None,
+        }
+    }
+
+    /// External RAM size in bytes, None for an unknown code.
+    pub fn ram_size(&self) -> Option<usize> {
+        match self.ram_size_code {
+            0x00 => Some(0),
+            // unused, 2KB in some docs
+            0x01 => Some(2 * 1024),
+            0x02 => Some(8 * 1024),
+            0x03 => Some(32 * 1024),
+            0x04 => Some(128 * 1024),
+            0x05 => Some(64 * 1024),
+            _ => None,
+        }
+    }
+
+    /// The mapper and hardware of the cartridge, None for an unknown type.
+    pub fn cartridge_type_name(&self) -> Option<&'static str> {
+        let name = match self.cartridge_type {
+            0x00 => "ROM ONLY",
+            0x01 => "MBC1",
+            0x02 => "MBC1+RAM",
+            0x03 => "MBC1+RAM+BATTERY",
+            0x05 => "MBC2",
+            0x06 => "MBC2+BATTERY",
+            0x08 => "ROM+RAM",
+            0x09 => "ROM+RAM+BATTERY",
+            0x0b => "MMM01",
+            0x0c => "MMM01+RAM",
+            0x0d => "MMM01+RAM+BATTERY",
+            0x0f => "MBC3+TIMER+BATTERY",
+            0x10 => "MBC3+TIMER+RAM+BATTERY",
+            0x11 => "MBC3",
+            0x12 => "MBC3+RAM",
+            0x13 => "MBC3+RAM+BATTERY",
+            0x19 => "MBC5",
+            0x1a => "MBC5+RAM",
+            0x1b => "MBC5+RAM+BATTERY",
+            0x1c => "MBC5+RUMBLE",
+            0x1d => "MBC5+RUMBLE+RAM",
+            0x1e => "MBC5+RUMBLE+RAM+BATTERY",
+            0x20 => "MBC6",
+            0x22 => "MBC7+SENSOR+RUMBLE+RAM+BATTERY",
+            0xfc => "POCKET CAMERA",
+            0xfd => "BANDAI TAMA5",
+            0xfe => "HuC3",
+            0xff => "HuC1+RAM+BATTERY",
+            _ => return None,
+        };
+
+        Some(name)
+    }
+
+    /// Everything wrong with the header of `rom`, empty for a valid ROM.
+    pub fn validate(&self, rom: &[u8]) -> Vec<Problem> {
+        let mut problems = vec![];
+
+        if rom[0x0104..=0x0133] != LOGO[..] {
+            problems.push(Problem::Logo);
+        }
+
+        let computed = header_checksum(rom);
+        if computed != self.header_checksum {
+            problems.push(Problem::HeaderChecksum {
+                header: self.header_checksum,
+                computed,
+            });
+        }
+
+        let computed = global_checksum(rom);
+        if computed != self.global_checksum {
+            problems.push(Problem::GlobalChecksum {
+                header: self.global_checksum,
+                computed,
+            });
+        }
+
+        if self.cartridge_type_name().is_none() {
+            problems.push(Problem::CartridgeType(self.cartridge_type));
+        }
+        match self.rom_size() {
+            Some(size) if size != rom.len() => problems.push(Problem::FileSize {
+                header: size,
+                file: rom.len(),
+            }),
+            Some(_) => {}
+            None => problems.push(Problem::RomSize(self.rom_size_code)),
+        }
+        if self.ram_size().is_none() {
+            problems.push(Problem::RamSize(self.ram_size_code));
+        }
+
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{global_checksum, header_checksum, Problem, RomHeader, LOGO};
+
+    fn rom() -> Vec<u8> {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0104..=0x0133].copy_from_slice(&LOGO);
+        rom[0x0134..0x0134 + 8].copy_from_slice(b"GBRUST\0\0");
+        rom[0x013f..=0x0142].copy_from_slice(b"ABCD");
+        rom[0x0143] = 0x80;
+        rom[0x0144..=0x0145].copy_from_slice(b"01");
+        rom[0x0146] = 0x03;
+        rom[0x0147] = 0x03;
+        rom[0x0149] = 0x02;
+        rom[0x014b] = 0x33;
+        rom[0x014d] = header_checksum(&rom);
+        let checksum = global_checksum(&rom);
+        rom[0x014e] = (checksum >> 8) as u8;
+        rom[0x014f] = checksum as u8;
+        rom
+    }
+
+    #[test]
+    fn test_parse() {
+        let rom = rom();
+        let header = RomHeader::parse(&rom).unwrap();
+
+        assert_eq!(header.title, "GBRUST");
+        assert_eq!(header.manufacturer_code.as_deref(), Some("ABCD"));
+        assert!(header.is_cgb() && !header.is_cgb_only() && header.is_sgb());
+        assert_eq!(header.licensee_code(), "01");
+        assert_eq!(header.cartridge_type_name(), Some("MBC1+RAM+BATTERY"));
+        assert_eq!(header.rom_size(), Some(0x8000));
+        assert_eq!(header.ram_size(), Some(0x2000));
+        assert_eq!(header.validate(&rom), vec![]);
+
+        assert!(RomHeader::parse(&rom[..0x014f]).is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut rom = rom();
+        rom[0x0104] = 0;
+        rom[0x0147] = 0x04;
+        rom.truncate(0x4000);
+        let header = RomHeader::parse(&rom).unwrap();
+
+        let problems = header.validate(&rom);
+        assert_eq!(problems[0], Problem::Logo);
+        assert!(matches!(problems[1], Problem::HeaderChecksum { .. }));
+        assert!(matches!(problems[2], Problem::GlobalChecksum { .. }));
+        assert_eq!(problems[3], Problem::CartridgeType(0x04));
+        assert_eq!(
+            problems[4],
+            Problem::FileSize {
+                header: 0x8000,
+                file: 0x4000
+            }
+        );
+    }
+}
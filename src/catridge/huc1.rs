@@ -0,0 +1,114 @@
+use crate::catridge::{banked_byte, Mapper, RAM_BANK_SIZE, ROM_BANK_SIZE};
+
+// Hudson HuC1: MBC1-like banking and an infrared LED / receiver instead of the RAM enable.
+// ref. https://gbdev.io/pandocs/HuC1.html
+
+pub struct HuC1 {
+    rom: Vec<u8>,
+    rom_bank: u8,
+    ram: Vec<u8>,
+    ram_bank: u8,
+    // $A000-$BFFF maps the IR register instead of RAM
+    ir_mode: bool,
+    // bit 0 of the IR register, nobody sees it
+    led: bool,
+}
+
+impl HuC1 {
+    pub fn new(rom: Vec<u8>, ram_size: usize) -> Self {
+        HuC1 {
+            rom,
+            rom_bank: 1,
+            ram: vec![0; ram_size],
+            ram_bank: 0,
+            ir_mode: false,
+            led: false,
+        }
+    }
+
+    fn ram_offset(&self, address: u16) -> Option<usize> {
+        match self.ram.is_empty() {
+            true => None,
+            false => Some(
+                (self.ram_bank as usize * RAM_BANK_SIZE + (address - 0xa000) as usize)
+                    % self.ram.len(),
+            ),
+        }
+    }
+}
+
+impl Mapper for HuC1 {
+    fn read(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x3fff => banked_byte(&self.rom, 0, ROM_BANK_SIZE, address),
+            0x4000..=0x7fff => {
+                banked_byte(&self.rom, self.rom_bank as usize, ROM_BANK_SIZE, address)
+            }
+            // IR register: $C1 when light is received, $C0 otherwise. There's no other side.
+            0xa000..=0xbfff if self.ir_mode => 0xc0,
+            0xa000..=0xbfff => match self.ram_offset(address) {
+                Some(offset) => self.ram[offset],
+                None => 0xff,
+            },
+            _ => panic!("invalid catridge read access {:#X}", address),
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        match address {
+            // $0E selects the IR register, anything else RAM. RAM is always enabled.
+            0x0000..=0x1fff => self.ir_mode = value == 0x0e,
+            // ROM Bank Number, 6 bits
+            0x2000..=0x3fff => self.rom_bank = value & 0x3f,
+            // RAM Bank Number, 2 bits
+            0x4000..=0x5fff => self.ram_bank = value & 0x03,
+            // no effect
+            0x6000..=0x7fff => {}
+            // bit 0 turns the IR LED on
+            0xa000..=0xbfff if self.ir_mode => self.led = value & 0x01 > 0,
+            0xa000..=0xbfff => {
+                if let Some(offset) = self.ram_offset(address) {
+                    self.ram[offset] = value;
+                }
+            }
+            _ => panic!("invalid catridge write access {:#X}", address),
+        }
+    }
+
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HuC1;
+    use crate::catridge::{Mapper, ROM_BANK_SIZE};
+
+    #[test]
+    fn test_huc1() {
+        let mut rom = vec![0; 64 * ROM_BANK_SIZE];
+        for bank in 0..64 {
+            rom[bank * ROM_BANK_SIZE] = bank as u8;
+        }
+        let mut catridge = HuC1::new(rom, 32 * 1024);
+
+        catridge.write(0x2000, 0x3f);
+        assert_eq!(catridge.read(0x4000), 0x3f);
+
+        // RAM without enabling it
+        catridge.write(0x4000, 0x02);
+        catridge.write(0xa000, 0x12);
+        assert_eq!(catridge.read(0xa000), 0x12);
+        catridge.write(0x4000, 0x00);
+        assert_eq!(catridge.read(0xa000), 0x00);
+
+        // IR register
+        catridge.write(0x0000, 0x0e);
+        catridge.write(0xa000, 0x01);
+        assert!(catridge.led);
+        assert_eq!(catridge.read(0xa000), 0xc0);
+        catridge.write(0x0000, 0x00);
+        assert_eq!(catridge.read(0xa000), 0x00);
+    }
+}
@@ -0,0 +1,234 @@
+use crate::catridge::{banked_byte, Mapper, RAM_BANK_SIZE, ROM_BANK_SIZE};
+
+// Hudson HuC3: MBC-like banking, an RTC and an infrared LED / receiver.
+// ref. https://gbdev.io/pandocs/HuC3.html
+//
+// The RTC is a microcontroller with 256 nibbles of memory. Commands are written to $A000 in
+// mode $B and run by clearing the semaphore in mode $D, results are read in mode $C.
+// Its time is the minute of the day and a day counter, copied to / from memory 0-5.
+
+// 4194304Hz
+const CLOCKS_PER_MINUTE: usize = 60 * 4_194_304;
+const MINUTES_PER_DAY: u16 = 24 * 60;
+
+pub struct HuC3 {
+    rom: Vec<u8>,
+    rom_bank: u8,
+    ram: Vec<u8>,
+    ram_bank: u8,
+    // what $A000-$BFFF maps, written to $0000-$1FFF
+    mode: u8,
+    // IR LED
+    led: bool,
+
+    // RTC
+    memory: [u8; 0x100],
+    address: u8,
+    command: u8,
+    response: u8,
+    minutes: u16,
+    days: u16,
+    clocks: usize,
+}
+
+impl HuC3 {
+    pub fn new(rom: Vec<u8>, ram_size: usize) -> Self {
+        HuC3 {
+            rom,
+            rom_bank: 1,
+            ram: vec![0; ram_size],
+            ram_bank: 0,
+            mode: 0,
+            led: false,
+            memory: [0; 0x100],
+            address: 0,
+            command: 0,
+            response: 0,
+            minutes: 0,
+            days: 0,
+            clocks: 0,
+        }
+    }
+
+    fn ram_offset(&self, address: u16) -> Option<usize> {
+        match self.ram.is_empty() {
+            true => None,
+            false => Some(
+                (self.ram_bank as usize * RAM_BANK_SIZE + (address - 0xa000) as usize)
+                    % self.ram.len(),
+            ),
+        }
+    }
+
+    // 12 bits as 3 nibbles, little endian
+    fn store(&mut self, offset: usize, value: u16) {
+        for i in 0..3 {
+            self.memory[offset + i] = (value >> (i * 4)) as u8 & 0x0f;
+        }
+    }
+
+    fn load(&self, offset: usize) -> u16 {
+        (0..3).fold(0, |value, i| {
+            value | (self.memory[offset + i] as u16) << (i * 4)
+        })
+    }
+
+    fn run_command(&mut self) {
+        let argument = self.command & 0x0f;
+        match self.command >> 4 {
+            // read the memory and move to the next address
+            0x1 => {
+                self.response = 0x10 | self.memory[self.address as usize];
+                self.address = self.address.wrapping_add(1);
+            }
+            // write the memory and move to the next address
+            0x3 => {
+                self.memory[self.address as usize] = argument;
+                self.address = self.address.wrapping_add(1);
+            }
+            // address, lower and upper nibble
+            0x4 => self.address = (self.address & 0xf0) | argument,
+            0x5 => self.address = (self.address & 0x0f) | argument << 4,
+            0x6 => match argument {
+                // copy the time to the memory
+                0x0 => {
+                    self.store(0, self.minutes);
+                    self.store(3, self.days);
+                }
+                // set the time from the memory
+                0x1 => {
+                    self.minutes = self.load(0) % MINUTES_PER_DAY;
+                    self.days = self.load(3);
+                }
+                // status, always fine
+                0x2 => self.response = 0x61,
+                // tone generator, not emulated
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+}
+
+impl Mapper for HuC3 {
+    fn read(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x3fff => banked_byte(&self.rom, 0, ROM_BANK_SIZE, address),
+            0x4000..=0x7fff => {
+                banked_byte(&self.rom, self.rom_bank as usize, ROM_BANK_SIZE, address)
+            }
+            0xa000..=0xbfff => match self.mode {
+                // RAM, read only in mode 0
+                0x0 | 0xa => match self.ram_offset(address) {
+                    Some(offset) => self.ram[offset],
+                    None => 0xff,
+                },
+                // result of the last command
+                0xc => self.response,
+                // semaphore: 1 when the RTC is ready, it always is
+                0xd => 0x01,
+                // IR register, no light received
+                0xe => 0xc0,
+                _ => 0xff,
+            },
+            _ => panic!("invalid catridge read access {:#X}", address),
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1fff => self.mode = value & 0x0f,
+            // ROM Bank Number, 7 bits
+            0x2000..=0x3fff => self.rom_bank = value & 0x7f,
+            // RAM Bank Number, 2 bits
+            0x4000..=0x5fff => self.ram_bank = value & 0x03,
+            0x6000..=0x7fff => {}
+            0xa000..=0xbfff => match self.mode {
+                0xa => {
+                    if let Some(offset) = self.ram_offset(address) {
+                        self.ram[offset] = value;
+                    }
+                }
+                0xb => self.command = value,
+                // clearing bit 0 runs the command
+                0xd if value & 0x01 == 0 => self.run_command(),
+                0xe => self.led = value & 0x01 > 0,
+                _ => {}
+            },
+            _ => panic!("invalid catridge write access {:#X}", address),
+        }
+    }
+
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    fn step(&mut self, clocks: usize) {
+        self.clocks += clocks;
+        while self.clocks >= CLOCKS_PER_MINUTE {
+            self.clocks -= CLOCKS_PER_MINUTE;
+            self.minutes += 1;
+            if self.minutes == MINUTES_PER_DAY {
+                self.minutes = 0;
+                self.days = (self.days + 1) & 0x0fff;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HuC3, CLOCKS_PER_MINUTE, MINUTES_PER_DAY};
+    use crate::catridge::{Mapper, ROM_BANK_SIZE};
+
+    fn command(catridge: &mut HuC3, command: u8) -> u8 {
+        catridge.write(0x0000, 0x0b);
+        catridge.write(0xa000, command);
+        catridge.write(0x0000, 0x0d);
+        catridge.write(0xa000, 0xfe);
+        catridge.write(0x0000, 0x0c);
+        catridge.read(0xa000)
+    }
+
+    #[test]
+    fn test_rtc() {
+        let mut catridge = HuC3::new(vec![0; 4 * ROM_BANK_SIZE], 0);
+
+        // day 2, 23:59
+        catridge.step(CLOCKS_PER_MINUTE * (2 * MINUTES_PER_DAY as usize + 23 * 60 + 59));
+        command(&mut catridge, 0x60);
+        command(&mut catridge, 0x40);
+        command(&mut catridge, 0x50);
+        let nibbles: Vec<u8> = (0..6)
+            .map(|_| command(&mut catridge, 0x10) & 0x0f)
+            .collect();
+        // 1439 = $59F, 2
+        assert_eq!(nibbles, vec![0xf, 0x9, 0x5, 0x2, 0x0, 0x0]);
+
+        catridge.step(CLOCKS_PER_MINUTE);
+        assert_eq!((catridge.minutes, catridge.days), (0, 3));
+
+        // set the time to day $123, minute 1
+        command(&mut catridge, 0x40);
+        for nibble in [0x1, 0x0, 0x0, 0x3, 0x2, 0x1].iter() {
+            command(&mut catridge, 0x30 | nibble);
+        }
+        command(&mut catridge, 0x61);
+        assert_eq!((catridge.minutes, catridge.days), (1, 0x123));
+    }
+
+    #[test]
+    fn test_ram() {
+        let mut catridge = HuC3::new(vec![0; 4 * ROM_BANK_SIZE], 32 * 1024);
+
+        catridge.write(0x0000, 0x0a);
+        catridge.write(0x4000, 0x01);
+        catridge.write(0xa000, 0x12);
+        assert_eq!(catridge.read(0xa000), 0x12);
+
+        // read only
+        catridge.write(0x0000, 0x00);
+        catridge.write(0xa000, 0x34);
+        assert_eq!(catridge.read(0xa000), 0x12);
+    }
+}
@@ -1,72 +1,261 @@
-use crate::catridge;
+use crate::catridge::header::LOGO;
+use crate::catridge::{banked_byte, Mapper, RAM_BANK_SIZE, ROM_BANK_SIZE};
 
 // ref. https://gbdev.gg8.se/wiki/articles/Memory_Bank_Controllers
+// ref. https://gbdev.io/pandocs/MBC1.html
 
-pub fn read(catridge: &catridge::Catridge, address: u16) -> u8 {
-    match address {
-        0x0000..=0x3fff => catridge.rom[address as usize],
-        // ROM Bank 01-7F (Read Only)
-        0x4000..=0x7fff => {
-            // the size of a bank is 16KB
-            let rom_offset = (16 * 1024) * catridge.rom_bank as u16;
-            catridge.rom[(rom_offset as usize) + ((address - 0x4000) as usize)]
-        }
-        // RAM Bank 00-03, if any
-        0xa000..=0xbfff => {
-            if !catridge.ram_enabled {
-                return 0xff;
-            }
+/// MBC1M: 1MB multicarts hold 4 games of 256KB, each with its own header.
+/// ref. https://gbdev.io/pandocs/MBC1.html#mbc1m-1-mib-multi-game-compilation-carts
+pub fn is_multicart(rom: &[u8]) -> bool {
+    // the logo of the game in bank $10, the menu is in bank 0
+    let logo = 0x10 * ROM_BANK_SIZE + 0x0104;
+    rom.len() == 64 * ROM_BANK_SIZE && rom[logo..logo + LOGO.len()] == LOGO[..]
+}
+
+pub struct Mbc1 {
+    rom: Vec<u8>,
+    // the ROM bank numbers are masked by the ROM size of the header, the unused upper bits
+    // of the bank number aren't connected to the ROM
+    rom_bank_mask: usize,
+    // lower bits of the ROM bank number (BANK1)
+    rom_bank: u8,
+
+    ram: Vec<u8>,
+    ram_enabled: bool,
+    // RAM bank, or upper bits of the ROM bank number (BANK2)
+    ram_bank: u8,
+
+    // mode 1: BANK2 also selects the bank of $0000-$3FFF and RAM
+    advanced_banking: bool,
+    // MBC1M: 1MB multicart with BANK2 wired to bit 4-5 of the ROM bank number
+    multicart: bool,
+}
 
-            let ram_offset = (8 * 1024) * catridge.ram_bank as u16;
-            catridge.ram[(ram_offset as usize) + (address - 0xa000) as usize]
+impl Mbc1 {
+    /// `rom_size` and `ram_size` are the sizes in the header.
+    pub fn new(rom: Vec<u8>, rom_size: usize, ram_size: usize) -> Self {
+        let multicart = is_multicart(&rom);
+        // the header of the menu of a multicart may only count its own banks
+        let banks = match multicart {
+            true => rom.len() / ROM_BANK_SIZE,
+            false => (rom_size / ROM_BANK_SIZE).next_power_of_two(),
+        };
+
+        Mbc1 {
+            multicart,
+            rom,
+            rom_bank_mask: banks - 1,
+            rom_bank: 1,
+            ram: vec![0; ram_size],
+            ram_enabled: false,
+            ram_bank: 0,
+            advanced_banking: false,
         }
-        _ => panic!("invalid catridge read access {:#X}", address),
     }
-}
 
-pub fn write(catridge: &mut catridge::Catridge, address: u16, value: u8) {
-    match address {
-        // RAM Enable (Write Only)
-        0x0000..=0x1fff => {
-            // any value with 0Ah in the lower 4 bits enables RAM, and any other value disables RAM
-            catridge.ram_enabled = (value & 0x0f) == 0x0a;
+    // BANK2 shifted to its place in the ROM bank number
+    fn upper_bank(&self) -> usize {
+        match self.multicart {
+            true => (self.ram_bank as usize) << 4,
+            false => (self.ram_bank as usize) << 5,
         }
-        // ROM Bank Number (Write Only)
-        0x2000..=0x3fff => {
-            // Writing to this address space selects the lower 5 bits of the ROM Bank Number (in range 01-1Fh).
-            catridge.rom_bank = (catridge.rom_bank & 0xe0) | (value & 0x1f);
-            catridge.update_rom_bank();
+    }
+
+    // the offset in RAM of an address in $A000-$BFFF, None when there's no RAM
+    fn ram_offset(&self, address: u16) -> Option<usize> {
+        if !self.ram_enabled || self.ram.is_empty() {
+            return None;
         }
-        // RAM Bank Number - or - Upper Bits of ROM Bank Number (Write Only)
-        0x4000..=0x5fff => {
-            // This 2bit register can be used to select a RAM Bank in range from 00-03h,
-            // or to specify the upper two bits (Bit 5-6) of the ROM Bank number
-            if catridge.rom_banking {
-                catridge.rom_bank = (catridge.rom_bank & 0x1f) | (value & 0xe0);
-                catridge.update_rom_bank();
-            } else {
-                catridge.ram_bank = value & 0x03;
+
+        // RAM banks are only switched in mode 1
+        let bank = match self.advanced_banking {
+            true => self.ram_bank as usize,
+            false => 0,
+        };
+
+        // 8KB and 2KB RAM ignore the bank and the upper address bits
+        Some((bank * RAM_BANK_SIZE + (address - 0xa000) as usize) % self.ram.len())
+    }
+}
+
+impl Mapper for Mbc1 {
+    fn read(&self, address: u16) -> u8 {
+        match address {
+            // ROM Bank 00 (Read Only), bank $20/$40/$60 in mode 1
+            0x0000..=0x3fff => {
+                let bank = match self.advanced_banking {
+                    true => self.upper_bank(),
+                    false => 0,
+                };
+                banked_byte(&self.rom, bank & self.rom_bank_mask, ROM_BANK_SIZE, address)
             }
-        }
-        // ROM/RAM Mode Select (Write Only)
-        0x6000..=0x7fff => {
-            // 0x0: ROM Banking mode
-            // 0x1: RAM Banking mode
-            catridge.rom_banking = (value & 0x01) == 0;
-            if catridge.rom_banking {
-                catridge.ram_bank = 0;
+            // ROM Bank 01-7F (Read Only)
+            0x4000..=0x7fff => {
+                let lower = match self.multicart {
+                    true => self.rom_bank as usize & 0x0f,
+                    false => self.rom_bank as usize,
+                };
+                let bank = (self.upper_bank() | lower) & self.rom_bank_mask;
+                banked_byte(&self.rom, bank, ROM_BANK_SIZE, address)
             }
+            // RAM Bank 00-03, if any
+            0xa000..=0xbfff => match self.ram_offset(address) {
+                Some(offset) => self.ram[offset],
+                None => 0xff,
+            },
+            _ => panic!("invalid catridge read access {:#X}", address),
         }
-        // RAM Bank 00-03, if any
-        0xa000..=0xbfff => {
-            if !catridge.ram_enabled {
-                return;
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        match address {
+            // RAM Enable (Write Only)
+            0x0000..=0x1fff => {
+                // any value with 0Ah in the lower 4 bits enables RAM, and any other value disables RAM
+                self.ram_enabled = (value & 0x0f) == 0x0a;
             }
+            // ROM Bank Number (Write Only)
+            0x2000..=0x3fff => {
+                // Writing to this address space selects the lower 5 bits of the ROM Bank Number (in range 01-1Fh).
+                // When 00h is written, the MBC translates that to bank 01h also,
+                // the upper bits don't matter: bank 20h, 40h and 60h can't be mapped to $4000-$7FFF
+                self.rom_bank = match value & 0x1f {
+                    0 => 1,
+                    bank => bank,
+                };
+            }
+            // RAM Bank Number - or - Upper Bits of ROM Bank Number (Write Only)
+            0x4000..=0x5fff => {
+                // This 2bit register selects a RAM Bank in range from 00-03h in mode 1,
+                // and the upper two bits (Bit 5-6) of the ROM Bank number in both modes
+                self.ram_bank = value & 0x03;
+            }
+            // Banking Mode Select (Write Only)
+            0x6000..=0x7fff => {
+                // 0x0: Simple Banking mode
+                // 0x1: RAM Banking mode / Advanced ROM Banking mode
+                self.advanced_banking = (value & 0x01) == 1;
+            }
+            // RAM Bank 00-03, if any
+            0xa000..=0xbfff => {
+                if let Some(offset) = self.ram_offset(address) {
+                    self.ram[offset] = value;
+                }
+            }
+
+            _ => panic!("invalid catridge write access {:#X}", address),
+        }
+    }
+
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Mbc1;
+    use crate::catridge::header::LOGO;
+    use crate::catridge::{Mapper, RAM_BANK_SIZE, ROM_BANK_SIZE};
+
+    // `banks` banks, each bank starting with its number
+    fn rom(banks: usize) -> Vec<u8> {
+        let mut rom = vec![0; banks * ROM_BANK_SIZE];
+        for bank in 0..banks {
+            rom[bank * ROM_BANK_SIZE] = bank as u8;
+        }
+        rom
+    }
+
+    // MBC1 ROM of `banks` banks
+    fn catridge(banks: usize, ram_size: usize) -> Mbc1 {
+        Mbc1::new(rom(banks), banks * ROM_BANK_SIZE, ram_size)
+    }
+
+    #[test]
+    fn test_rom_banks() {
+        // 2MB
+        let mut catridge = catridge(128, 0);
+        assert_eq!(catridge.read(0x4000), 1);
+
+        catridge.write(0x2000, 0x12);
+        assert_eq!(catridge.read(0x4000), 0x12);
+        catridge.write(0x4000, 0x02);
+        assert_eq!(catridge.read(0x4000), 0x52);
+        assert_eq!(catridge.read(0x0000), 0x00);
+
+        // bank $40 becomes $41
+        catridge.write(0x2000, 0x00);
+        assert_eq!(catridge.read(0x4000), 0x41);
+
+        // mode 1 maps bank $40 to $0000-$3FFF
+        catridge.write(0x6000, 0x01);
+        assert_eq!(catridge.read(0x0000), 0x40);
+        assert_eq!(catridge.read(0x4000), 0x41);
+    }
+
+    #[test]
+    fn test_rom_size() {
+        // 256KB: bank numbers wrap at 16 banks
+        let mut catridge = catridge(16, 0);
+        catridge.write(0x2000, 0x13);
+        assert_eq!(catridge.read(0x4000), 0x03);
+        catridge.write(0x4000, 0x03);
+        assert_eq!(catridge.read(0x4000), 0x03);
+        catridge.write(0x6000, 0x01);
+        assert_eq!(catridge.read(0x0000), 0x00);
+
+        // 512KB file of a 256KB ROM: masked by the size of the header
+        let mut catridge = Mbc1::new(rom(32), 16 * ROM_BANK_SIZE, 0);
+        catridge.write(0x2000, 0x13);
+        assert_eq!(catridge.read(0x4000), 0x03);
+
+        // 128KB file of a 256KB ROM: wraps around the file
+        let mut catridge = Mbc1::new(rom(8), 16 * ROM_BANK_SIZE, 0);
+        catridge.write(0x2000, 0x0b);
+        assert_eq!(catridge.read(0x4000), 0x03);
+    }
+
+    #[test]
+    fn test_ram_banks() {
+        // 32KB RAM
+        let mut catridge = catridge(4, 32 * 1024);
+        assert_eq!(catridge.read(0xa000), 0xff);
+        catridge.write(0x0000, 0x0a);
+
+        catridge.write(0xa000, 0x11);
+        catridge.write(0x4000, 0x02);
+        // mode 0 always uses bank 0
+        assert_eq!(catridge.read(0xa000), 0x11);
+
+        catridge.write(0x6000, 0x01);
+        catridge.write(0xa000, 0x22);
+        assert_eq!(catridge.ram[2 * RAM_BANK_SIZE], 0x22);
+
+        catridge.write(0x6000, 0x00);
+        assert_eq!(catridge.read(0xa000), 0x11);
+
+        // disabled
+        catridge.write(0x0000, 0x00);
+        assert_eq!(catridge.read(0xa000), 0xff);
+    }
 
-            let ram_offset = (8 * 1024) * catridge.ram_bank as u16;
-            catridge.ram[(ram_offset as usize) + (address - 0xa000) as usize] = value;
+    #[test]
+    fn test_multicart() {
+        let mut rom = rom(64);
+        for game in 0..4 {
+            let logo = game * 0x10 * ROM_BANK_SIZE + 0x0104;
+            rom[logo..logo + LOGO.len()].copy_from_slice(&LOGO);
         }
+        // the header of the menu is the one of a 256KB ROM
+        let mut catridge = Mbc1::new(rom, 16 * ROM_BANK_SIZE, 0);
+        assert!(catridge.multicart);
 
-        _ => panic!("invalid catridge write access {:#X}", address),
+        // BANK2 selects the game, BANK1 bit 4 is ignored
+        catridge.write(0x4000, 0x01);
+        catridge.write(0x2000, 0x13);
+        assert_eq!(catridge.read(0x4000), 0x13);
+        catridge.write(0x6000, 0x01);
+        assert_eq!(catridge.read(0x0000), 0x10);
     }
 }
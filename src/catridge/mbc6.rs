@@ -0,0 +1,264 @@
+use crate::catridge::{banked_byte, Mapper, ROM_BANK_SIZE};
+
+// MBC6: ROM and flash in two banks of 8KB at $4000-$5FFF and $6000-$7FFF, RAM in two
+// banks of 4KB at $A000-$AFFF and $B000-$BFFF.
+// ref. https://gbdev.io/pandocs/MBC6.html
+//
+// The 1MB flash (Macronix MX29F008) takes commands after the unlock sequence: $AA written
+// to $5555 then $55 to $2AAA (addresses in the flash).
+
+const BANK_SIZE: usize = 8 * 1024;
+const RAM_BANK_SIZE: usize = 4 * 1024;
+const FLASH_SIZE: usize = 1024 * 1024;
+
+pub struct Mbc6 {
+    rom: Vec<u8>,
+    // bank number of $4000-$5FFF and $6000-$7FFF
+    rom_banks: [u8; 2],
+    // the bank is in the flash instead of the ROM
+    flash_selected: [bool; 2],
+
+    ram: Vec<u8>,
+    ram_enabled: bool,
+    // bank number of $A000-$AFFF and $B000-$BFFF
+    ram_banks: [u8; 2],
+
+    flash: Vec<u8>,
+    flash_enabled: bool,
+    flash_write_enabled: bool,
+    // writes of the unlock sequence received
+    unlock: u8,
+    // $80 received, the next command erases
+    erase: bool,
+    // $A0 received, the next write programs a byte
+    program: bool,
+    // reads return the manufacturer and device IDs
+    id: bool,
+}
+
+impl Mbc6 {
+    pub fn new(rom: Vec<u8>, ram_size: usize) -> Self {
+        Mbc6 {
+            rom,
+            rom_banks: [0; 2],
+            flash_selected: [false; 2],
+            ram: vec![0; ram_size],
+            ram_enabled: false,
+            ram_banks: [0; 2],
+            flash: vec![0xff; FLASH_SIZE],
+            flash_enabled: false,
+            flash_write_enabled: false,
+            unlock: 0,
+            erase: false,
+            program: false,
+            id: false,
+        }
+    }
+
+    // 0 for $4000-$5FFF / $A000-$AFFF, 1 for $6000-$7FFF / $B000-$BFFF
+    fn window(address: u16) -> usize {
+        (address as usize >> 13) & 0x01
+    }
+
+    fn flash_offset(&self, address: u16) -> Option<usize> {
+        let window = Mbc6::window(address);
+        match self.flash_selected[window] && self.flash_enabled {
+            true => Some(
+                (self.rom_banks[window] as usize * BANK_SIZE
+                    + (address as usize & (BANK_SIZE - 1)))
+                    % FLASH_SIZE,
+            ),
+            false => None,
+        }
+    }
+
+    fn ram_offset(&self, address: u16) -> Option<usize> {
+        if !self.ram_enabled || self.ram.is_empty() {
+            return None;
+        }
+        let bank = self.ram_banks[(address as usize >> 12) & 0x01] as usize;
+        Some((bank * RAM_BANK_SIZE + (address as usize & (RAM_BANK_SIZE - 1))) % self.ram.len())
+    }
+
+    fn write_flash(&mut self, offset: usize, value: u8) {
+        if self.program {
+            // programming only clears bits
+            if self.flash_write_enabled {
+                self.flash[offset] &= value;
+            }
+            self.program = false;
+            return;
+        }
+
+        match (self.unlock, offset, value) {
+            (_, _, 0xf0) => {
+                // reset
+                self.unlock = 0;
+                self.erase = false;
+                self.id = false;
+            }
+            (0, 0x5555, 0xaa) | (1, 0x2aaa, 0x55) => self.unlock += 1,
+            (2, _, 0x30) if self.erase => {
+                // sector erase, at the address of the sector
+                if self.flash_write_enabled {
+                    let sector = offset & !(BANK_SIZE - 1);
+                    self.flash[sector..sector + BANK_SIZE].fill(0xff);
+                }
+                self.erase = false;
+                self.unlock = 0;
+            }
+            (2, 0x5555, command) => {
+                match command {
+                    0x10 if self.erase => {
+                        // chip erase
+                        if self.flash_write_enabled {
+                            self.flash.fill(0xff);
+                        }
+                        self.erase = false;
+                    }
+                    0x80 => self.erase = true,
+                    0x90 => self.id = true,
+                    0xa0 => self.program = true,
+                    _ => {}
+                }
+                self.unlock = 0;
+            }
+            _ => self.unlock = 0,
+        }
+    }
+}
+
+impl Mapper for Mbc6 {
+    fn read(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x3fff => banked_byte(&self.rom, 0, ROM_BANK_SIZE, address),
+            0x4000..=0x7fff => match self.flash_offset(address) {
+                // manufacturer, device
+                Some(offset) if self.id => match offset & 0xff {
+                    0x00 => 0xc2,
+                    0x01 => 0x81,
+                    _ => 0x00,
+                },
+                Some(offset) => self.flash[offset],
+                None if self.flash_selected[Mbc6::window(address)] => 0xff,
+                None => banked_byte(
+                    &self.rom,
+                    self.rom_banks[Mbc6::window(address)] as usize,
+                    BANK_SIZE,
+                    address,
+                ),
+            },
+            0xa000..=0xbfff => match self.ram_offset(address) {
+                Some(offset) => self.ram[offset],
+                None => 0xff,
+            },
+            _ => panic!("invalid catridge read access {:#X}", address),
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x03ff => self.ram_enabled = value == 0x0a,
+            0x0400..=0x07ff => self.ram_banks[0] = value & 0x07,
+            0x0800..=0x0bff => self.ram_banks[1] = value & 0x07,
+            0x0c00..=0x0fff => self.flash_enabled = value & 0x01 > 0,
+            0x1000..=0x1fff => self.flash_write_enabled = value & 0x01 > 0,
+            // ROM / flash bank number, and $00: ROM, $08: flash
+            0x2000..=0x27ff => self.rom_banks[0] = value & 0x7f,
+            0x2800..=0x2fff => self.flash_selected[0] = value == 0x08,
+            0x3000..=0x37ff => self.rom_banks[1] = value & 0x7f,
+            0x3800..=0x3fff => self.flash_selected[1] = value == 0x08,
+            0x4000..=0x7fff => {
+                if let Some(offset) = self.flash_offset(address) {
+                    self.write_flash(offset, value);
+                }
+            }
+            0xa000..=0xbfff => {
+                if let Some(offset) = self.ram_offset(address) {
+                    self.ram[offset] = value;
+                }
+            }
+            _ => panic!("invalid catridge write access {:#X}", address),
+        }
+    }
+
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Mbc6, BANK_SIZE, RAM_BANK_SIZE};
+    use crate::catridge::Mapper;
+
+    fn catridge() -> Mbc6 {
+        let mut rom = vec![0; 64 * BANK_SIZE];
+        for bank in 0..64 {
+            rom[bank * BANK_SIZE] = bank as u8;
+        }
+        Mbc6::new(rom, 32 * 1024)
+    }
+
+    #[test]
+    fn test_banks() {
+        let mut catridge = catridge();
+        catridge.write(0x2000, 0x05);
+        catridge.write(0x3000, 0x22);
+        assert_eq!(catridge.read(0x4000), 0x05);
+        assert_eq!(catridge.read(0x6000), 0x22);
+        assert_eq!(catridge.read(0x0000), 0x00);
+
+        catridge.write(0x0000, 0x0a);
+        catridge.write(0x0400, 0x01);
+        catridge.write(0x0800, 0x07);
+        catridge.write(0xa000, 0x11);
+        catridge.write(0xb000, 0x77);
+        assert_eq!(catridge.ram[RAM_BANK_SIZE], 0x11);
+        assert_eq!(catridge.ram[7 * RAM_BANK_SIZE], 0x77);
+    }
+
+    // the unlock sequence with bank 2 at $4000 and 1 at $6000
+    fn unlock(catridge: &mut Mbc6) {
+        catridge.write(0x2000, 0x02);
+        catridge.write(0x3000, 0x01);
+        catridge.write(0x5555, 0xaa);
+        catridge.write(0x6aaa, 0x55);
+    }
+
+    fn command(catridge: &mut Mbc6, command: u8) {
+        unlock(catridge);
+        catridge.write(0x5555, command);
+    }
+
+    #[test]
+    fn test_flash() {
+        let mut catridge = catridge();
+        catridge.write(0x0c00, 0x01);
+        catridge.write(0x1000, 0x01);
+        catridge.write(0x2800, 0x08);
+        catridge.write(0x3800, 0x08);
+
+        command(&mut catridge, 0x90);
+        assert_eq!(catridge.read(0x4000), 0xc2);
+        catridge.write(0x4000, 0xf0);
+
+        // program bank 3
+        command(&mut catridge, 0xa0);
+        catridge.write(0x3000, 0x03);
+        catridge.write(0x6010, 0x5a);
+        assert_eq!(catridge.read(0x6010), 0x5a);
+
+        // sector erase
+        command(&mut catridge, 0x80);
+        unlock(&mut catridge);
+        catridge.write(0x2000, 0x03);
+        catridge.write(0x4000, 0x30);
+        catridge.write(0x3000, 0x03);
+        assert_eq!(catridge.read(0x6010), 0xff);
+
+        // the ROM again
+        catridge.write(0x3800, 0x00);
+        assert_eq!(catridge.read(0x6000), 0x03);
+    }
+}
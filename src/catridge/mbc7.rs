@@ -0,0 +1,346 @@
+use crate::catridge::{banked_byte, Mapper, ROM_BANK_SIZE};
+
+// MBC7: ROM banking, a 2-axis accelerometer and a 256 bytes EEPROM (93LC56) instead of RAM.
+// ref. https://gbdev.io/pandocs/MBC7.html
+//
+// $A000-$AFFF maps registers, selected by bit 4-7 of the address, when both RAM enables are
+// set. The accelerometer values are latched by writing $55 then $AA.
+
+// latched value when level, and the change for 1g
+const TILT_CENTER: i32 = 0x81d0;
+const TILT_1G: f32 = 0x70 as f32;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    // waiting for the start bit
+    Idle,
+    // opcode (2 bits) and address (8 bits)
+    Command {
+        bits: u16,
+        count: u8,
+    },
+    // the word being read, MSB first
+    Read {
+        word: u16,
+        count: u8,
+    },
+    // the word being written, to an address or to all of them
+    Data {
+        address: Option<usize>,
+        bits: u16,
+        count: u8,
+    },
+}
+
+// Microwire serial EEPROM of 128 words of 16 bits.
+// ref. https://ww1.microchip.com/downloads/en/DeviceDoc/21794F.pdf
+struct Eeprom {
+    data: [u16; 128],
+    // chip select, clock, data in, data out
+    cs: bool,
+    clk: bool,
+    di: bool,
+    output: bool,
+    write_enabled: bool,
+    state: State,
+}
+
+impl Eeprom {
+    fn new() -> Self {
+        Eeprom {
+            data: [0xffff; 128],
+            cs: false,
+            clk: false,
+            di: false,
+            output: true,
+            write_enabled: false,
+            state: State::Idle,
+        }
+    }
+
+    fn read(&self) -> u8 {
+        (self.cs as u8) << 7 | (self.clk as u8) << 6 | (self.di as u8) << 1 | self.output as u8
+    }
+
+    // bit 7: CS, bit 6: CLK, bit 1: DI
+    fn write(&mut self, value: u8) {
+        let cs = value & 0x80 > 0;
+        let clk = value & 0x40 > 0;
+        let di = value & 0x02 > 0;
+
+        if !cs {
+            self.state = State::Idle;
+        } else if clk && !self.clk {
+            // rising edge
+            self.state = self.clock(di);
+        }
+
+        self.cs = cs;
+        self.clk = clk;
+        self.di = di;
+    }
+
+    fn clock(&mut self, di: bool) -> State {
+        match self.state {
+            State::Idle if di => State::Command { bits: 0, count: 0 },
+            State::Idle => State::Idle,
+            State::Command { bits, count } => {
+                let bits = bits << 1 | di as u16;
+                match count + 1 {
+                    10 => self.run(bits),
+                    count => State::Command { bits, count },
+                }
+            }
+            State::Read { word, count } => {
+                self.output = word & 0x8000 > 0;
+                match count - 1 {
+                    0 => State::Idle,
+                    count => State::Read {
+                        word: word << 1,
+                        count,
+                    },
+                }
+            }
+            State::Data {
+                address,
+                bits,
+                count,
+            } => {
+                let bits = bits << 1 | di as u16;
+                match count + 1 {
+                    16 => {
+                        if self.write_enabled {
+                            match address {
+                                Some(address) => self.data[address] = bits,
+                                None => self.data.fill(bits),
+                            }
+                        }
+                        // ready
+                        self.output = true;
+                        State::Idle
+                    }
+                    count => State::Data {
+                        address,
+                        bits,
+                        count,
+                    },
+                }
+            }
+        }
+    }
+
+    fn run(&mut self, command: u16) -> State {
+        let address = (command & 0x7f) as usize;
+        match command >> 8 {
+            // READ, after a dummy 0
+            0b10 => {
+                self.output = false;
+                State::Read {
+                    word: self.data[address],
+                    count: 16,
+                }
+            }
+            // WRITE
+            0b01 => State::Data {
+                address: Some(address),
+                bits: 0,
+                count: 0,
+            },
+            // ERASE
+            0b11 => {
+                if self.write_enabled {
+                    self.data[address] = 0xffff;
+                }
+                self.output = true;
+                State::Idle
+            }
+            // the upper bits of the address select the command
+            _ => match (command >> 6) & 0x03 {
+                // EWDS
+                0b00 => {
+                    self.write_enabled = false;
+                    State::Idle
+                }
+                // WRAL
+                0b01 => State::Data {
+                    address: None,
+                    bits: 0,
+                    count: 0,
+                },
+                // ERAL
+                0b10 => {
+                    if self.write_enabled {
+                        self.data.fill(0xffff);
+                    }
+                    self.output = true;
+                    State::Idle
+                }
+                // EWEN
+                _ => {
+                    self.write_enabled = true;
+                    State::Idle
+                }
+            },
+        }
+    }
+}
+
+pub struct Mbc7 {
+    rom: Vec<u8>,
+    rom_bank: u8,
+    // $0000-$1FFF ($0A) and $4000-$5FFF ($40)
+    ram_enabled: [bool; 2],
+    // from the frontend, in g
+    tilt: (f32, f32),
+    // latched accelerometer values
+    x: u16,
+    y: u16,
+    // $55 written, waiting for $AA to latch
+    erased: bool,
+    eeprom: Eeprom,
+}
+
+impl Mbc7 {
+    pub fn new(rom: Vec<u8>) -> Self {
+        Mbc7 {
+            rom,
+            rom_bank: 1,
+            ram_enabled: [false; 2],
+            tilt: (0.0, 0.0),
+            x: 0x8000,
+            y: 0x8000,
+            erased: false,
+            eeprom: Eeprom::new(),
+        }
+    }
+
+    fn latch(tilt: f32) -> u16 {
+        (TILT_CENTER + (tilt.clamp(-1.0, 1.0) * TILT_1G) as i32) as u16
+    }
+}
+
+impl Mapper for Mbc7 {
+    fn read(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x3fff => banked_byte(&self.rom, 0, ROM_BANK_SIZE, address),
+            0x4000..=0x7fff => {
+                banked_byte(&self.rom, self.rom_bank as usize, ROM_BANK_SIZE, address)
+            }
+            0xa000..=0xafff if self.ram_enabled == [true; 2] => match (address >> 4) & 0x0f {
+                0x2 => self.x as u8,
+                0x3 => (self.x >> 8) as u8,
+                0x4 => self.y as u8,
+                0x5 => (self.y >> 8) as u8,
+                0x6 => 0x00,
+                0x8 => self.eeprom.read(),
+                _ => 0xff,
+            },
+            0xa000..=0xbfff => 0xff,
+            _ => panic!("invalid catridge read access {:#X}", address),
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1fff => self.ram_enabled[0] = value == 0x0a,
+            // ROM Bank Number, 7 bits
+            0x2000..=0x3fff => self.rom_bank = value & 0x7f,
+            0x4000..=0x5fff => self.ram_enabled[1] = value == 0x40,
+            0x6000..=0x7fff => {}
+            0xa000..=0xafff if self.ram_enabled == [true; 2] => {
+                match ((address >> 4) & 0x0f, value) {
+                    (0x0, 0x55) => {
+                        self.x = 0x8000;
+                        self.y = 0x8000;
+                        self.erased = true;
+                    }
+                    (0x1, 0xaa) if self.erased => {
+                        self.x = Mbc7::latch(self.tilt.0);
+                        self.y = Mbc7::latch(self.tilt.1);
+                        self.erased = false;
+                    }
+                    (0x8, _) => self.eeprom.write(value),
+                    _ => {}
+                }
+            }
+            0xa000..=0xbfff => {}
+            _ => panic!("invalid catridge write access {:#X}", address),
+        }
+    }
+
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    fn set_tilt(&mut self, x: f32, y: f32) {
+        self.tilt = (x, y);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Mbc7;
+    use crate::catridge::{Mapper, ROM_BANK_SIZE};
+
+    fn catridge() -> Mbc7 {
+        let mut catridge = Mbc7::new(vec![0; 8 * ROM_BANK_SIZE]);
+        catridge.write(0x0000, 0x0a);
+        catridge.write(0x4000, 0x40);
+        catridge
+    }
+
+    #[test]
+    fn test_accelerometer() {
+        let mut catridge = catridge();
+        catridge.set_tilt(1.0, -0.5);
+
+        // not latched
+        catridge.write(0xa010, 0xaa);
+        assert_eq!(catridge.read(0xa020), 0x00);
+
+        catridge.write(0xa000, 0x55);
+        assert_eq!(catridge.read(0xa030), 0x80);
+        catridge.write(0xa010, 0xaa);
+        let x = catridge.read(0xa020) as u16 | (catridge.read(0xa030) as u16) << 8;
+        let y = catridge.read(0xa040) as u16 | (catridge.read(0xa050) as u16) << 8;
+        assert_eq!((x, y), (0x81d0 + 0x70, 0x81d0 - 0x38));
+
+        // disabled
+        catridge.write(0x4000, 0x00);
+        assert_eq!(catridge.read(0xa020), 0xff);
+    }
+
+    // sends `bits` bits of `value`, MSB first, and returns DO after each of them
+    fn send(catridge: &mut Mbc7, value: u32, bits: u32) -> u32 {
+        (0..bits).rev().fold(0, |output, i| {
+            let di = ((value >> i) as u8 & 0x01) << 1;
+            catridge.write(0xa080, 0x80 | di);
+            catridge.write(0xa080, 0xc0 | di);
+            output << 1 | (catridge.read(0xa080) & 0x01) as u32
+        })
+    }
+
+    fn command(catridge: &mut Mbc7, value: u32, bits: u32) -> u32 {
+        catridge.write(0xa080, 0x00);
+        send(catridge, value, bits)
+    }
+
+    #[test]
+    fn test_eeprom() {
+        let mut catridge = catridge();
+
+        // write protected
+        command(&mut catridge, 0x503 << 16 | 0x1234, 27);
+        assert_eq!(command(&mut catridge, 0x603 << 16, 27) & 0xffff, 0xffff);
+
+        // EWEN, WRITE, READ
+        command(&mut catridge, 0x4c0, 11);
+        command(&mut catridge, 0x503 << 16 | 0x1234, 27);
+        assert_eq!(catridge.eeprom.data[3], 0x1234);
+        assert_eq!(command(&mut catridge, 0x603 << 16, 27) & 0xffff, 0x1234);
+
+        // ERAL
+        command(&mut catridge, 0x480, 11);
+        assert_eq!(catridge.eeprom.data[3], 0xffff);
+    }
+}
@@ -0,0 +1,233 @@
+use crate::catridge::header::{header_checksum, RomHeader, LOGO};
+use crate::catridge::{banked_byte, Mapper, RAM_BANK_SIZE, ROM_BANK_SIZE};
+
+// MMM01: multicarts whose menu, in the last 32KB of the ROM, selects a game and locks the
+// mapper to its banks. The game then sees an MBC1.
+// ref. https://gbdev.io/pandocs/MMM01.html
+//
+// In unmapped mode all the registers are writable. Once mapped, only the MBC1 ones are, and
+// the bits of the ROM and RAM bank numbers masked by the menu stay fixed.
+
+/// Whether the menu at the end of `rom` has a valid MMM01 header.
+pub fn is_mmm01(rom: &[u8]) -> bool {
+    if rom.len() < 2 * ROM_BANK_SIZE {
+        return false;
+    }
+    let menu = &rom[rom.len() - 2 * ROM_BANK_SIZE..];
+    matches!(menu[0x0147], 0x0b..=0x0d)
+        && menu[0x0104..0x0134] == LOGO
+        && menu[0x014d] == header_checksum(menu)
+}
+
+pub struct Mmm01 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    ram_enabled: bool,
+    // the menu locked the mapping
+    mapped: bool,
+
+    // ROM bank number: high (2 bits), mid (2 bits), low (5 bits)
+    rom_bank_low: u8,
+    rom_bank_mid: u8,
+    rom_bank_high: u8,
+    // bits 1-4 of the low ROM bank number fixed by the menu
+    rom_mask: u8,
+
+    // RAM bank number: high (2 bits), low (2 bits)
+    ram_bank_low: u8,
+    ram_bank_high: u8,
+    // bits of the low RAM bank number fixed by the menu
+    ram_mask: u8,
+
+    // MBC1 mode 1: RAM banking
+    advanced_banking: bool,
+    // the menu can prevent the game from changing the mode
+    mode_locked: bool,
+}
+
+impl Mmm01 {
+    pub fn new(rom: Vec<u8>) -> Self {
+        // the RAM size is in the header of the menu
+        let menu = &rom[rom.len() - 2 * ROM_BANK_SIZE..];
+        let ram_size = RomHeader::parse(menu)
+            .ok()
+            .and_then(|header| header.ram_size())
+            .unwrap_or(0);
+
+        Mmm01 {
+            rom,
+            ram: vec![0; ram_size],
+            ram_enabled: false,
+            mapped: false,
+            rom_bank_low: 0,
+            rom_bank_mid: 0,
+            rom_bank_high: 0,
+            rom_mask: 0,
+            ram_bank_low: 0,
+            ram_bank_high: 0,
+            ram_mask: 0,
+            advanced_banking: false,
+            mode_locked: false,
+        }
+    }
+
+    fn rom_bank(&self, address: u16) -> usize {
+        // the menu: the last 32KB
+        if !self.mapped {
+            let banks = self.rom.len() / ROM_BANK_SIZE;
+            return match address {
+                0x0000..=0x3fff => banks - 2,
+                _ => banks - 1,
+            };
+        }
+
+        let fixed = self.rom_mask << 1;
+        let game = (self.rom_bank_high as usize) << 7
+            | (self.rom_bank_mid as usize) << 5
+            | (self.rom_bank_low & fixed) as usize;
+        match address {
+            0x0000..=0x3fff => game,
+            _ => {
+                // bank 0 becomes 1 as on MBC1, within the game
+                let bank = match self.rom_bank_low & !fixed & 0x1f {
+                    0 => 1,
+                    bank => bank,
+                };
+                game | bank as usize
+            }
+        }
+    }
+
+    fn ram_offset(&self, address: u16) -> Option<usize> {
+        if !self.ram_enabled || self.ram.is_empty() {
+            return None;
+        }
+
+        let low = match self.advanced_banking {
+            true => self.ram_bank_low,
+            false => self.ram_bank_low & self.ram_mask,
+        };
+        let bank = (self.ram_bank_high << 2 | low) as usize;
+        Some((bank * RAM_BANK_SIZE + (address - 0xa000) as usize) % self.ram.len())
+    }
+
+    // `register` with the bits of `value` which aren't fixed by `mask` once mapped
+    fn masked(&self, register: u8, value: u8, mask: u8) -> u8 {
+        match self.mapped {
+            true => (register & mask) | (value & !mask),
+            false => value,
+        }
+    }
+}
+
+impl Mapper for Mmm01 {
+    fn read(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x7fff => {
+                banked_byte(&self.rom, self.rom_bank(address), ROM_BANK_SIZE, address)
+            }
+            0xa000..=0xbfff => match self.ram_offset(address) {
+                Some(offset) => self.ram[offset],
+                None => 0xff,
+            },
+            _ => panic!("invalid catridge read access {:#X}", address),
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        match address {
+            // bit 6: map the game, bit 4-5: RAM bank mask, bit 0-3: RAM enable
+            0x0000..=0x1fff => {
+                self.ram_enabled = (value & 0x0f) == 0x0a;
+                if !self.mapped {
+                    self.ram_mask = (value >> 4) & 0x03;
+                    self.mapped = value & 0x40 > 0;
+                }
+            }
+            // bit 5-6: ROM bank mid, bit 0-4: ROM bank low
+            0x2000..=0x3fff => {
+                self.rom_bank_low =
+                    self.masked(self.rom_bank_low, value & 0x1f, self.rom_mask << 1);
+                if !self.mapped {
+                    self.rom_bank_mid = (value >> 5) & 0x03;
+                }
+            }
+            // bit 6: lock the mode, bit 4-5: ROM bank high, bit 2-3: RAM bank high,
+            // bit 0-1: RAM bank low
+            0x4000..=0x5fff => {
+                self.ram_bank_low = self.masked(self.ram_bank_low, value & 0x03, self.ram_mask);
+                if !self.mapped {
+                    self.ram_bank_high = (value >> 2) & 0x03;
+                    self.rom_bank_high = (value >> 4) & 0x03;
+                    self.mode_locked = value & 0x40 > 0;
+                }
+            }
+            // bit 6: multiplex (not emulated), bit 2-5: ROM bank mask, bit 0: mode
+            0x6000..=0x7fff => {
+                if !self.mode_locked {
+                    self.advanced_banking = value & 0x01 > 0;
+                }
+                if !self.mapped {
+                    self.rom_mask = (value >> 2) & 0x0f;
+                }
+            }
+            0xa000..=0xbfff => {
+                if let Some(offset) = self.ram_offset(address) {
+                    self.ram[offset] = value;
+                }
+            }
+            _ => panic!("invalid catridge write access {:#X}", address),
+        }
+    }
+
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{header_checksum, is_mmm01, Mmm01, LOGO};
+    use crate::catridge::{Mapper, ROM_BANK_SIZE};
+
+    #[test]
+    fn test_mmm01() {
+        // 256KB, the menu in banks 14-15
+        let mut rom = vec![0; 16 * ROM_BANK_SIZE];
+        for bank in 0..16 {
+            rom[bank * ROM_BANK_SIZE] = bank as u8;
+        }
+        // the header of the menu needs the logo and the checksum
+        let menu = 14 * ROM_BANK_SIZE;
+        rom[menu + 0x0147] = 0x0b;
+        assert!(!is_mmm01(&rom));
+        rom[menu + 0x0104..menu + 0x0134].copy_from_slice(&LOGO);
+        assert!(!is_mmm01(&rom));
+        rom[menu + 0x014d] = header_checksum(&rom[menu..]);
+        assert!(is_mmm01(&rom));
+        let mut catridge = Mmm01::new(rom);
+
+        assert_eq!(catridge.read(0x0000), 14);
+        assert_eq!(catridge.read(0x4000), 15);
+
+        // the game of 4 banks at bank 4: bits 2-4 of the bank number are fixed
+        catridge.write(0x2000, 0x04);
+        catridge.write(0x6000, 0b1110 << 2);
+        assert_eq!(catridge.read(0x0000), 14);
+        catridge.write(0x0000, 0x40);
+        assert_eq!(catridge.read(0x0000), 4);
+        assert_eq!(catridge.read(0x4000), 5);
+
+        catridge.write(0x2000, 0x02);
+        assert_eq!(catridge.read(0x4000), 6);
+        catridge.write(0x2000, 0x1f);
+        assert_eq!(catridge.read(0x4000), 7);
+        catridge.write(0x2000, 0x00);
+        assert_eq!(catridge.read(0x4000), 5);
+
+        // locked: the mask can't change anymore
+        catridge.write(0x6000, 0x00);
+        catridge.write(0x2000, 0x1f);
+        assert_eq!(catridge.read(0x4000), 7);
+    }
+}
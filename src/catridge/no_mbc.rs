@@ -1,11 +1,32 @@
-use crate::catridge;
+use crate::catridge::Mapper;
 
 // ref. https://gbdev.gg8.se/wiki/articles/Memory_Bank_Controllers
 
-pub fn read(catridge: &catridge::Catridge, address: u16) -> u8 {
-    catridge.rom[address as usize]
+pub struct NoMbc {
+    rom: Vec<u8>,
 }
 
-pub fn write(_catridge: &mut catridge::Catridge, _address: u16, _value: u8) {
-    // only rom
+impl NoMbc {
+    pub fn new(rom: Vec<u8>) -> Self {
+        NoMbc { rom }
+    }
+}
+
+impl Mapper for NoMbc {
+    fn read(&self, address: u16) -> u8 {
+        match address {
+            // a file smaller than 32KB reads $FF past its end
+            0x0000..=0x7fff => self.rom.get(address as usize).copied().unwrap_or(0xff),
+            // no RAM
+            _ => 0xff,
+        }
+    }
+
+    fn write(&mut self, _address: u16, _value: u8) {
+        // only rom
+    }
+
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
 }
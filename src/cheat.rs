@@ -0,0 +1,264 @@
+// Game Genie and GameShark cheat codes.
+//
+// Game Genie codes patch the ROM as it is read: ABC-DEF or ABC-DEF-GHI
+//   AB: new value, FCDE xor $F000: ROM address,
+//   GI rotated right by 2 xor $BA: the value to replace (the code only applies when the ROM
+//   holds it, e.g. in the right bank), H: unused
+// GameShark codes write RAM at every VBlank: ABCDEFGH
+//   AB: RAM bank, CD: value, GHEF: RAM address
+//   The bank isn't switched, the value goes to the bank mapped at the time.
+//
+// ref. https://gbdev.gg8.se/wiki/articles/Gameshark_and_Game_Genie_Codes
+//
+// A cheat file holds one cheat per line, its codes joined with `+` and an optional name:
+//
+// ```text
+// # Super Mario Land
+// 00A-17B-C49 infinite lives
+// 01FF40C0+01FF41C0 two codes
+// ```
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Code {
+    GameGenie {
+        address: u16,
+        value: u8,
+        compare: Option<u8>,
+    },
+    GameShark {
+        bank: u8,
+        address: u16,
+        value: u8,
+    },
+}
+
+fn hex_digits(text: &str) -> Result<Vec<u8>, String> {
+    text.chars()
+        .map(|c| {
+            c.to_digit(16)
+                .map(|d| d as u8)
+                .ok_or(format!("invalid character {:?} in {}", c, text))
+        })
+        .collect()
+}
+
+impl Code {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+        let digits = hex_digits(&text.replace('-', ""))?;
+        let byte = |i: usize| digits[i] << 4 | digits[i + 1];
+
+        match digits.len() {
+            // Game Genie
+            6 | 9 => {
+                let address = ((digits[5] as u16) << 12
+                    | (digits[2] as u16) << 8
+                    | (digits[3] as u16) << 4
+                    | digits[4] as u16)
+                    ^ 0xf000;
+                if address >= 0x8000 {
+                    return Err(format!("{}: {:04X} isn't a ROM address", text, address));
+                }
+
+                let compare = match digits.len() {
+                    9 => Some((digits[6] << 4 | digits[8]).rotate_right(2) ^ 0xba),
+                    _ => None,
+                };
+
+                Ok(Code::GameGenie {
+                    address,
+                    value: byte(0),
+                    compare,
+                })
+            }
+            // GameShark
+            8 if !text.contains('-') => {
+                let address = (byte(6) as u16) << 8 | byte(4) as u16;
+                if !(0xa000..=0xdfff).contains(&address) {
+                    return Err(format!("{}: {:04X} isn't a RAM address", text, address));
+                }
+
+                Ok(Code::GameShark {
+                    bank: byte(0),
+                    address,
+                    value: byte(2),
+                })
+            }
+            _ => Err(format!(
+                "{}: expected a Game Genie (ABC-DEF or ABC-DEF-GHI) or GameShark (ABCDEFGH) code",
+                text
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cheat {
+    pub name: String,
+    pub codes: Vec<Code>,
+    pub enabled: bool,
+}
+
+impl Cheat {
+    /// Parses `CODE[+CODE...] [name]`.
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let line = line.trim();
+        let (codes, name) = match line.find(char::is_whitespace) {
+            Some(i) => (&line[..i], line[i..].trim()),
+            None => (line, line),
+        };
+
+        Ok(Cheat {
+            name: name.to_string(),
+            codes: codes
+                .split('+')
+                .map(Code::parse)
+                .collect::<Result<_, _>>()?,
+            enabled: true,
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct Cheats {
+    cheats: Vec<Cheat>,
+}
+
+impl Cheats {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        Cheats::parse(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut cheats = Cheats::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let cheat = Cheat::parse(line).map_err(|e| format!("line {}: {}", i + 1, e))?;
+            cheats.add(cheat);
+        }
+
+        Ok(cheats)
+    }
+
+    pub fn add(&mut self, cheat: Cheat) {
+        self.cheats.push(cheat);
+    }
+
+    pub fn cheats(&self) -> &[Cheat] {
+        &self.cheats
+    }
+
+    /// Turns the cheat on or off. Returns the cheat, None when there's no such cheat.
+    pub fn toggle(&mut self, index: usize) -> Option<&Cheat> {
+        let cheat = self.cheats.get_mut(index)?;
+        cheat.enabled = !cheat.enabled;
+        Some(cheat)
+    }
+
+    fn codes(&self) -> impl Iterator<Item = &Code> {
+        self.cheats
+            .iter()
+            .filter(|cheat| cheat.enabled)
+            .flat_map(|cheat| cheat.codes.iter())
+    }
+
+    /// The ROM byte at `address` with the Game Genie codes applied, `value` is the ROM's.
+    pub fn patch_rom(&self, address: u16, value: u8) -> u8 {
+        for code in self.codes() {
+            if let Code::GameGenie {
+                address: a,
+                value: v,
+                compare,
+            } = *code
+            {
+                if a == address && (compare.is_none() || compare == Some(value)) {
+                    return v;
+                }
+            }
+        }
+
+        value
+    }
+
+    /// `(address, value)` to write at VBlank for the GameShark codes.
+    pub fn ram_writes(&self) -> Vec<(u16, u8)> {
+        self.codes()
+            .filter_map(|code| match *code {
+                Code::GameShark { address, value, .. } => Some((address, value)),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cheat, Cheats, Code};
+
+    #[test]
+    fn test_parse_code() {
+        assert_eq!(
+            Code::parse("00A-17B-C49"),
+            Ok(Code::GameGenie {
+                address: 0x4a17,
+                value: 0x00,
+                compare: Some(0xc8),
+            })
+        );
+        assert_eq!(
+            Code::parse("3EB-01F"),
+            Ok(Code::GameGenie {
+                address: 0x0b01,
+                value: 0x3e,
+                compare: None,
+            })
+        );
+        assert_eq!(
+            Code::parse("010238CD"),
+            Ok(Code::GameShark {
+                bank: 0x01,
+                address: 0xcd38,
+                value: 0x02,
+            })
+        );
+
+        // ROM address for a GameShark code
+        assert!(Code::parse("01020040").is_err());
+        // RAM address for a Game Genie code
+        assert!(Code::parse("00A-170-C49").is_err());
+        assert!(Code::parse("00A-17B-C4").is_err());
+        assert!(Code::parse("00G-17B-C49").is_err());
+    }
+
+    #[test]
+    fn test_cheats() {
+        let mut cheats =
+            Cheats::parse("# comment\n00A-17B-C49 infinite lives\n\n010238CD+010339CD\n").unwrap();
+        assert_eq!(cheats.cheats().len(), 2);
+        assert_eq!(cheats.cheats()[0].name, "infinite lives");
+
+        assert_eq!(cheats.patch_rom(0x4a17, 0xc8), 0x00);
+        // another bank
+        assert_eq!(cheats.patch_rom(0x4a17, 0x12), 0x12);
+        assert_eq!(cheats.ram_writes(), vec![(0xcd38, 0x02), (0xcd39, 0x03)]);
+
+        assert!(!cheats.toggle(0).unwrap().enabled);
+        assert_eq!(cheats.patch_rom(0x4a17, 0xc8), 0xc8);
+        assert!(cheats.toggle(2).is_none());
+
+        assert!(Cheats::parse("00A-170 RAM address").is_err());
+        assert_eq!(
+            Cheat::parse("3EB-01F").unwrap().codes,
+            vec![Code::parse("3EB-01F").unwrap()]
+        );
+    }
+}
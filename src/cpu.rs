@@ -20,6 +20,31 @@ pub struct Cpu {
     bc: register::Register,
     de: register::Register,
     hl: register::Register,
+
+    hooks: Vec<(Trigger, Hook)>,
+}
+
+/// Called with the registers before the instruction it was registered for executes.
+pub type Hook = Box<dyn FnMut(&Registers)>;
+
+enum Trigger {
+    Opcode(u8),
+    Address(u16),
+}
+
+/// Snapshot of the CPU registers.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Registers {
+    pub a: u8,
+    pub f: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub sp: u16,
+    pub pc: u16,
 }
 
 impl fmt::Debug for Cpu {
@@ -48,24 +73,27 @@ impl fmt::Debug for Cpu {
 
 impl Cpu {
     pub fn new_with_boot_rom(boot_rom_name: &str, rom_name: &str) -> Self {
-        Cpu {
-            mmu: Mmu::new_with_boot_rom(boot_rom_name, rom_name),
-            pc: 0x0000,
-            sp: 0,
-            t: 0,
-            ime: false,
-            halt: false,
-            af: register::Register::new(0, 0xfff0),
-            bc: register::Register::new(0, 0),
-            de: register::Register::new(0, 0),
-            hl: register::Register::new(0, 0),
-        }
+        Cpu::with_mmu(Mmu::new_with_boot_rom(boot_rom_name, rom_name))
     }
 
     pub fn new(rom_name: &str) -> Self {
-        Cpu {
-            mmu: Mmu::new(rom_name),
-            pc: 0x0100,
+        Cpu::with_mmu(Mmu::new(rom_name))
+    }
+
+    /// `rom` is the unpacked ROM (see `rom::unpack`).
+    pub fn from_rom(boot_rom: Option<Vec<u8>>, rom: Vec<u8>) -> Result<Self, String> {
+        Ok(Cpu::with_mmu(Mmu::from_rom(boot_rom, rom)?))
+    }
+
+    // Starts in the boot ROM, or at $0100 in the state the boot ROM leaves.
+    fn with_mmu(mmu: Mmu) -> Self {
+        let boot_rom = mmu.boot_rom_enabled;
+        let mut cpu = Cpu {
+            mmu,
+            pc: match boot_rom {
+                true => 0x0000,
+                false => 0x0100,
+            },
             sp: 0,
             t: 0,
             ime: false,
@@ -74,6 +102,63 @@ impl Cpu {
             bc: register::Register::new(0, 0),
             de: register::Register::new(0, 0),
             hl: register::Register::new(0, 0),
+            hooks: vec![],
+        };
+
+        // the boot rom leaves A = 0x11 on CGB, which games use to detect CGB
+        if !boot_rom && cpu.mmu.ppu.is_cgb() {
+            cpu.af.set_high(0x11);
+        }
+
+        cpu
+    }
+
+    /// Address of the next instruction.
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    /// Calls `hook` every time an instruction with `opcode` is about to execute
+    /// (e.g. 0x40 `LD B,B`, used by test ROMs as a debugger breakpoint).
+    pub fn on_opcode(&mut self, opcode: u8, hook: Hook) {
+        self.hooks.push((Trigger::Opcode(opcode), hook));
+    }
+
+    /// Calls `hook` every time the instruction at `address` is about to execute.
+    pub fn on_breakpoint(&mut self, address: u16, hook: Hook) {
+        self.hooks.push((Trigger::Address(address), hook));
+    }
+
+    fn run_hooks(&mut self) {
+        if self.hooks.is_empty() {
+            return;
+        }
+
+        let opcode = self.mmu.read_byte(self.pc);
+        let registers = self.registers();
+        for (trigger, hook) in self.hooks.iter_mut() {
+            let triggered = match trigger {
+                Trigger::Opcode(o) => *o == opcode,
+                Trigger::Address(a) => *a == registers.pc,
+            };
+            if triggered {
+                hook(&registers);
+            }
+        }
+    }
+
+    pub fn registers(&self) -> Registers {
+        Registers {
+            a: self.af.high(),
+            f: self.af.low(),
+            b: self.bc.high(),
+            c: self.bc.low(),
+            d: self.de.high(),
+            e: self.de.low(),
+            h: self.hl.high(),
+            l: self.hl.low(),
+            sp: self.sp,
+            pc: self.pc,
         }
     }
 
@@ -86,9 +171,13 @@ impl Cpu {
                 self.t += 4;
             }
         } else {
+            self.run_hooks();
             self.fetch_and_execute();
         }
 
+        // the CPU is stalled while HDMA copies data
+        self.t += self.mmu.take_stalled_clocks();
+
         self.mmu.step(self.t);
 
         // self.mmu.ppu.debug = true;
@@ -278,16 +367,20 @@ impl Cpu {
 #[cfg(test)]
 mod tests {
     use super::Cpu;
+    use crate::serial::Console;
 
     #[test]
     fn test_cpu_instrs() {
         let mut cpu = Cpu::new("roms/cpu_instrs.gb");
+        let console = Console::default();
+        let output = console.output();
+        cpu.mmu.serial.connect(Box::new(console));
 
         let steps: u64 = 25000000;
         for _ in 1..=steps {
             cpu.step();
         }
 
-        assert_eq!(cpu.mmu.serial_port, "cpu_instrs\n\n01:ok  02:ok  03:ok  04:ok  05:ok  06:ok  07:ok  08:ok  09:ok  10:ok  11:ok  \n\nPassed all tests\n");
+        assert_eq!(*output.borrow(), "cpu_instrs\n\n01:ok  02:ok  03:ok  04:ok  05:ok  06:ok  07:ok  08:ok  09:ok  10:ok  11:ok  \n\nPassed all tests\n");
     }
 }
@@ -1196,7 +1196,7 @@ pub fn execute(opecode: &opcode::Opcode, cpu: &mut Cpu) {
         }
 
         // 8. STOP
-        0x10 => {}
+        0x10 => cpu.mmu.stop(),
 
         // EI
         0xfb => {
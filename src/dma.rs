@@ -0,0 +1,102 @@
+// OAM DMA Transfer
+// ref. https://gbdev.io/pandocs/OAM_DMA_Transfer.html
+//
+// Writing $XX to $FF46 copies $XX00-$XX9F to OAM ($FE00-$FE9F), one byte per M-cycle.
+// The transfer starts 1 M-cycle after the write and takes 160 M-cycles.
+
+const LENGTH: u16 = 0xa0;
+// M-cycles between the write to $FF46 and the first byte transferred
+const STARTUP_DELAY: usize = 1;
+
+#[derive(Default)]
+pub struct Dma {
+    // $FF46 - DMA Transfer and Start Address
+    register: u8,
+    source: u16,
+    // bytes transferred so far
+    index: u16,
+    active: bool,
+    // a (re)started transfer waiting for its startup delay
+    pending: Option<u16>,
+    delay: usize,
+    // the last byte transferred, seen by the CPU when it accesses the same bus
+    pub value: u8,
+}
+
+impl Dma {
+    pub fn start(&mut self, value: u8) {
+        self.register = value;
+
+        // $E000-$FFFF are not wired to OAM DMA, the upper half of WRAM is read instead
+        let source = match value {
+            0xe0..=0xff => ((value as u16) << 8) - 0x2000,
+            _ => (value as u16) << 8,
+        };
+
+        // the previous transfer keeps running until the new one takes over
+        self.pending = Some(source);
+        self.delay = STARTUP_DELAY;
+    }
+
+    pub fn read(&self) -> u8 {
+        self.register
+    }
+
+    /// OAM is owned by the DMA while a transfer is in progress.
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Source address of the transfer in progress.
+    pub fn source(&self) -> u16 {
+        self.source
+    }
+
+    /// Advances the transfer by one M-cycle.
+    /// Returns (source, destination) of the byte to copy during that M-cycle.
+    pub fn tick(&mut self) -> Option<(u16, u16)> {
+        if let Some(source) = self.pending {
+            if self.delay == 0 {
+                self.pending = None;
+                self.source = source;
+                self.index = 0;
+                self.active = true;
+            } else {
+                self.delay -= 1;
+            }
+        }
+
+        if !self.active {
+            return None;
+        }
+
+        let transfer = (self.source + self.index, 0xfe00 + self.index);
+        self.index += 1;
+        if self.index == LENGTH {
+            self.active = false;
+        }
+
+        Some(transfer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Dma;
+
+    #[test]
+    fn test_dma_timing() {
+        let mut dma = Dma::default();
+        dma.start(0xfe);
+
+        // startup delay
+        assert_eq!(dma.tick(), None);
+        // $FE00 is read from WRAM
+        assert_eq!(dma.tick(), Some((0xde00, 0xfe00)));
+        for i in 1..0xa0 {
+            assert_eq!(dma.tick(), Some((0xde00 + i, 0xfe00 + i)));
+        }
+        assert!(!dma.is_active());
+        assert_eq!(dma.tick(), None);
+    }
+}
@@ -0,0 +1,266 @@
+// The emulator core, driven one frame at a time by a frontend.
+
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::cpu::Cpu;
+use crate::joypad::Button;
+use crate::movie::{self, Movie, Recorder};
+use crate::pacing::{self, Pacer};
+use crate::palette::Palette;
+use crate::{image, ppu, rom, sgb};
+
+// 4.194304 MHz
+pub const CLOCK_HZ: u64 = 4_194_304;
+// https://mgba-emu.github.io/gbdoc/
+// > One frame takes 70224 cycles
+pub const FRAME_CLOCKS: usize = 456 * 154;
+
+/// Displays frames and collects input (a window, a terminal, nothing...).
+pub trait Frontend {
+    /// Shows a frame of `width` x `height` 0xAARRGGBB pixels.
+    fn present(&mut self, buffer: &[u32], width: usize, height: usize);
+
+    /// Feeds input to the emulator (e.g. `Emulator::set_button`).
+    /// Returns false when the user quits.
+    fn poll_input(&mut self, emulator: &mut Emulator) -> bool;
+
+    /// Sound samples of the last frame. Sound isn't emulated yet, so this is never called.
+    fn queue_audio(&mut self, _samples: &[i16]) {}
+
+    /// Called after every frame run with the time it lasts on the real hardware.
+    /// `Emulator::run` already waits to keep the speed set in `Emulator::pacer`.
+    fn report_timing(&mut self, _emulated: Duration) {}
+}
+
+pub struct Emulator {
+    pub cpu: Cpu,
+    /// Speed of `run`.
+    pub pacer: Pacer,
+    // palettes of monochrome games cycled by `next_palette`
+    palettes: Vec<Palette>,
+    palette_index: usize,
+    // frames run since power-on
+    frame: u64,
+    // the movie played and its next frame
+    playback: Option<(Movie, usize)>,
+    recorder: Option<Recorder>,
+    // the recorder continues the played movie, the played frames aren't recorded again
+    rerecording: bool,
+}
+
+impl Emulator {
+    pub fn new(rom_name: &str) -> Self {
+        Emulator::with_cpu(Cpu::new(rom_name))
+    }
+
+    pub fn new_with_boot_rom(boot_rom_name: &str, rom_name: &str) -> Self {
+        Emulator::with_cpu(Cpu::new_with_boot_rom(boot_rom_name, rom_name))
+    }
+
+    /// Runs a ROM from memory, a ROM or a zip / gzip archive.
+    /// Fails on an archive without a ROM, or a ROM with an invalid or unsupported header.
+    pub fn from_rom(boot_rom: Option<&[u8]>, rom: &[u8]) -> io::Result<Self> {
+        let rom = rom::unpack(rom)?;
+        let cpu = Cpu::from_rom(boot_rom.map(|b| b.to_vec()), rom)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(Emulator::with_cpu(cpu))
+    }
+
+    fn with_cpu(cpu: Cpu) -> Self {
+        Emulator {
+            cpu,
+            pacer: Pacer::default(),
+            palettes: vec![],
+            palette_index: 0,
+            frame: 0,
+            playback: None,
+            recorder: None,
+            rerecording: false,
+        }
+    }
+
+    /// Runs until the PPU enters VBlank, or for the duration of a frame while the LCD is off.
+    /// Returns the number of clocks run (at normal speed).
+    pub fn run_frame(&mut self) -> usize {
+        self.update_movie();
+        self.frame += 1;
+
+        let mut clocks = 0;
+        loop {
+            let tick = self.cpu.step();
+            // the CPU runs twice as fast in CGB double speed mode
+            clocks += match self.cpu.mmu.is_double_speed() {
+                true => tick / 2,
+                false => tick,
+            };
+
+            if self.cpu.mmu.take_vblank() || clocks >= FRAME_CLOCKS {
+                return clocks;
+            }
+        }
+    }
+
+    /// Frames run since power-on.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Plays the input of `movie` instead of the frontend's, from power-on.
+    pub fn play_movie(&mut self, movie: Movie) -> Result<(), String> {
+        movie.check(self.cpu.mmu.rom())?;
+        if self.frame > 0 {
+            return Err("movies are played from power-on".to_string());
+        }
+        if movie.version != movie::VERSION {
+            eprintln!(
+                "the movie was recorded with gbrust {}, the playback may differ",
+                movie.version
+            );
+        }
+
+        self.playback = Some((movie, 0));
+        Ok(())
+    }
+
+    /// Whether a movie is being played.
+    pub fn is_playing(&self) -> bool {
+        self.playback.is_some()
+    }
+
+    /// Records the joypad state of the next frames.
+    pub fn record_movie(&mut self, recorder: Recorder) {
+        self.recorder = Some(recorder);
+    }
+
+    /// Plays `movie`, then records the input after it with `recorder`, which appends to the
+    /// movie file (see `Recorder::append`).
+    pub fn rerecord_movie(&mut self, movie: Movie, recorder: Recorder) -> Result<(), String> {
+        self.play_movie(movie)?;
+        self.recorder = Some(recorder);
+        self.rerecording = true;
+        Ok(())
+    }
+
+    // Sets the joypad state played for the next frame, and records it.
+    fn update_movie(&mut self) {
+        let mut played = false;
+        if let Some((movie, frame)) = &mut self.playback {
+            match movie.frames.get(*frame) {
+                Some(state) => {
+                    self.cpu.mmu.joypad.set_state(*state);
+                    *frame += 1;
+                    played = true;
+                }
+                // the frontend takes over
+                None => self.playback = None,
+            }
+        }
+        if played && self.rerecording {
+            return;
+        }
+
+        if let Some(recorder) = &mut self.recorder {
+            if let Err(e) = recorder.record(self.cpu.mmu.joypad.state()) {
+                eprintln!("stopped recording: {}", e);
+                self.recorder = None;
+            }
+        }
+    }
+
+    /// The picture to display: the SGB picture with its border, or the LCD.
+    /// Returns `(buffer, width, height)`.
+    pub fn screen(&self) -> (&[u32], usize, usize) {
+        match &self.cpu.mmu.sgb {
+            Some(sgb) => (&sgb.buffer, sgb::WIDTH, sgb::HEIGHT),
+            None => (&self.cpu.mmu.ppu.buffer, ppu::WIDTH, ppu::HEIGHT),
+        }
+    }
+
+    /// Presses or releases a button, ignored while a movie is played.
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        if let Some((movie, frame)) = &self.playback {
+            if *frame < movie.frames.len() {
+                return;
+            }
+        }
+        self.cpu.mmu.joypad.set(button, pressed);
+    }
+
+    /// Tilt for cartridges with an accelerometer (MBC7), -1.0 to 1.0 to the right and down.
+    pub fn set_tilt(&mut self, x: f32, y: f32) {
+        self.cpu.mmu.set_tilt(x, y);
+    }
+
+    /// Palettes of monochrome games, the first one is used right away.
+    pub fn set_palettes(&mut self, palettes: Vec<Palette>) {
+        if let Some(palette) = palettes.first() {
+            self.cpu.mmu.ppu.set_palette(*palette);
+        }
+        self.palettes = palettes;
+        self.palette_index = 0;
+    }
+
+    /// Switches to the next palette set with `set_palettes`.
+    pub fn next_palette(&mut self) {
+        if self.palettes.is_empty() {
+            return;
+        }
+
+        self.palette_index = (self.palette_index + 1) % self.palettes.len();
+        self.cpu
+            .mmu
+            .ppu
+            .set_palette(self.palettes[self.palette_index]);
+    }
+
+    /// Saves the screen enlarged by `scale` to the first unused screenshot_NNN.png
+    /// in the current directory.
+    pub fn save_screenshot(&self, scale: usize) -> io::Result<PathBuf> {
+        let (buffer, width, height) = self.screen();
+        let path = (0..)
+            .map(|i| PathBuf::from(format!("screenshot_{:03}.png", i)))
+            .find(|path| !path.exists())
+            .unwrap();
+
+        let scaled = image::scale(width, height, buffer, scale);
+        image::write_png(&path, width * scale, height * scale, &scaled)?;
+
+        Ok(path)
+    }
+
+    /// Turns the cheat `index` on or off. Returns a description of its new state.
+    pub fn toggle_cheat(&mut self, index: usize) -> Option<String> {
+        let cheat = self.cpu.mmu.cheats.toggle(index)?;
+        let state = match cheat.enabled {
+            true => "on",
+            false => "off",
+        };
+
+        Some(format!("cheat {} {}", cheat.name, state))
+    }
+
+    /// Runs frames until the frontend quits.
+    pub fn run(&mut self, frontend: &mut dyn Frontend) {
+        loop {
+            if self.pacer.should_run() {
+                let emulated = pacing::clocks_duration(self.run_frame());
+
+                let (buffer, width, height) = self.screen();
+                frontend.present(buffer, width, height);
+                frontend.report_timing(emulated);
+                self.pacer.wait(emulated);
+            } else {
+                // the frontend keeps showing the picture while paused
+                let (buffer, width, height) = self.screen();
+                frontend.present(buffer, width, height);
+                self.pacer.idle();
+            }
+
+            if !frontend.poll_input(self) {
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,321 @@
+// Runs test ROMs and checks the way each suite reports its result.
+//
+// blargg:   prints "Passed" or "Failed" to the serial port
+// mooneye:  executes LD B,B with the Fibonacci numbers 3/5/8/13/21/34 in B/C/D/E/H/L on success
+// dmg-acid2: executes LD B,B once the picture is drawn, the screenshot is the result
+//
+// A manifest lists one ROM per line: `rom condition frames [reference.png]`
+//
+// ```text
+// # rom           condition  frames  reference
+// picture.gb      frames     10      picture.png
+// cpu_instrs.gb   blargg     4000
+// ```
+//
+// Paths are relative to the manifest. A reference screenshot is compared pixel by pixel
+// at the end of the run; on mismatch the actual picture and a diff are written out.
+
+use std::cell::Cell;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::cpu::{Cpu, Registers};
+use crate::emulator::Emulator;
+use crate::image;
+use crate::ppu;
+use crate::serial::Console;
+
+// LD B,B
+const DEBUG_OPCODE: u8 = 0x40;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Condition {
+    /// Runs all the frames, only the reference screenshot is checked.
+    Frames,
+    /// "Passed" or "Failed" on the serial port.
+    Blargg,
+    /// LD B,B with the Fibonacci numbers in the registers.
+    Mooneye,
+    /// LD B,B, then the reference screenshot is checked.
+    Acid2,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TestRom {
+    pub rom: PathBuf,
+    pub condition: Condition,
+    /// Frames to run at most.
+    pub frames: u64,
+    pub reference: Option<PathBuf>,
+}
+
+impl TestRom {
+    /// Runs the ROM. Pictures of a failed screenshot comparison are written to `output_dir`.
+    pub fn run<P: AsRef<Path>>(&self, output_dir: P) -> Result<(), String> {
+        let mut emulator = Emulator::new(&self.rom.to_string_lossy());
+        let cpu = &mut emulator.cpu;
+        let console = Console::default();
+        let output = console.output();
+        cpu.mmu.serial.connect(Box::new(console));
+        let mooneye = Mooneye::attach(cpu);
+
+        let stopped = self.run_frames(&mut emulator, || match self.condition {
+            Condition::Frames => false,
+            Condition::Blargg => {
+                let output = output.borrow();
+                output.contains("Passed") || output.contains("Failed")
+            }
+            Condition::Mooneye | Condition::Acid2 => mooneye.is_finished(),
+        });
+
+        if !stopped && self.condition != Condition::Frames {
+            return Err(format!("no result after {} frames", self.frames));
+        }
+
+        match self.condition {
+            Condition::Blargg if !output.borrow().contains("Passed") => {
+                return Err(format!("serial output: {:?}", output.borrow()));
+            }
+            Condition::Mooneye => mooneye.result().unwrap_or(Ok(()))?,
+            _ => {}
+        }
+
+        match &self.reference {
+            Some(reference) => {
+                self.check_screenshot(&emulator.cpu.mmu.ppu.buffer, reference, output_dir)
+            }
+            None => Ok(()),
+        }
+    }
+
+    // Returns true when `stop` returned true before the frame limit.
+    fn run_frames<F: Fn() -> bool>(&self, emulator: &mut Emulator, stop: F) -> bool {
+        for _ in 0..self.frames {
+            emulator.run_frame();
+            if stop() {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    fn check_screenshot<P: AsRef<Path>>(
+        &self,
+        buffer: &[u32],
+        reference: &Path,
+        output_dir: P,
+    ) -> Result<(), String> {
+        let (width, height, expected) = image::read_png(reference)
+            .map_err(|e| format!("failed to read {}: {}", reference.display(), e))?;
+        if (width, height) != (ppu::WIDTH, ppu::HEIGHT) {
+            return Err(format!(
+                "{} is {}x{}, expected {}x{}",
+                reference.display(),
+                width,
+                height,
+                ppu::WIDTH,
+                ppu::HEIGHT
+            ));
+        }
+
+        let (different, diff) = diff(buffer, &expected);
+        if different == 0 {
+            return Ok(());
+        }
+
+        let name = self.rom.file_stem().unwrap_or_default().to_string_lossy();
+        let output_dir = output_dir.as_ref();
+        let actual_path = output_dir.join(format!("{}.actual.png", name));
+        let diff_path = output_dir.join(format!("{}.diff.png", name));
+        fs::create_dir_all(output_dir)
+            .and_then(|_| image::write_png(&actual_path, width, height, buffer))
+            .and_then(|_| image::write_png(&diff_path, width, height, &diff))
+            .map_err(|e| format!("failed to write {}: {}", diff_path.display(), e))?;
+
+        Err(format!(
+            "{} pixels differ from {}, see {}",
+            different,
+            reference.display(),
+            diff_path.display()
+        ))
+    }
+}
+
+/// Detects the end of a mooneye test: LD B,B with the result in the registers.
+pub struct Mooneye {
+    registers: Rc<Cell<Option<Registers>>>,
+}
+
+impl Mooneye {
+    pub fn attach(cpu: &mut Cpu) -> Self {
+        let registers = Rc::new(Cell::new(None));
+        let hook_registers = Rc::clone(&registers);
+        cpu.on_opcode(
+            DEBUG_OPCODE,
+            Box::new(move |r: &Registers| {
+                // the first LD B,B reports the result
+                if hook_registers.get().is_none() {
+                    hook_registers.set(Some(*r));
+                }
+            }),
+        );
+
+        Mooneye { registers }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.registers.get().is_some()
+    }
+
+    /// None while the test is running.
+    pub fn result(&self) -> Option<Result<(), String>> {
+        self.registers.get().map(|r| check_mooneye(&r))
+    }
+}
+
+fn check_mooneye(registers: &Registers) -> Result<(), String> {
+    let values = [
+        registers.b,
+        registers.c,
+        registers.d,
+        registers.e,
+        registers.h,
+        registers.l,
+    ];
+
+    match values {
+        [3, 5, 8, 13, 21, 34] => Ok(()),
+        [0x42, 0x42, 0x42, 0x42, 0x42, 0x42] => Err("failed".to_string()),
+        _ => Err(format!("unexpected registers {:?}", registers)),
+    }
+}
+
+// Number of different pixels, and a picture of the differences:
+// different pixels are red, the others a faded copy of the expected picture.
+fn diff(actual: &[u32], expected: &[u32]) -> (usize, Vec<u32>) {
+    let mut different = 0;
+    let diff = actual
+        .iter()
+        .zip(expected.iter())
+        .map(|(a, e)| {
+            if a == e {
+                // halfway to white
+                0xFF000000 | (((e & 0x00FEFEFE) >> 1) + 0x007F7F7F)
+            } else {
+                different += 1;
+                0xFFFF0000
+            }
+        })
+        .collect();
+
+    (different, diff)
+}
+
+fn parse_condition(name: &str) -> Option<Condition> {
+    match name {
+        "frames" => Some(Condition::Frames),
+        "blargg" => Some(Condition::Blargg),
+        "mooneye" => Some(Condition::Mooneye),
+        "acid2" => Some(Condition::Acid2),
+        _ => None,
+    }
+}
+
+/// Reads a manifest of test ROMs.
+pub fn load_manifest<P: AsRef<Path>>(path: P) -> io::Result<Vec<TestRom>> {
+    let text = fs::read_to_string(&path)?;
+    let base = path.as_ref().parent().unwrap_or_else(|| Path::new(""));
+
+    parse_manifest(&text, base).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+pub fn parse_manifest(text: &str, base: &Path) -> Result<Vec<TestRom>, String> {
+    let mut tests = vec![];
+
+    for (i, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let (rom, condition, frames, reference) = match fields.as_slice() {
+            [rom, condition, frames] => (rom, condition, frames, None),
+            [rom, condition, frames, reference] => (rom, condition, frames, Some(reference)),
+            _ => return Err(format!("line {}: expected `rom condition frames`", i + 1)),
+        };
+
+        let condition = parse_condition(condition).ok_or(format!(
+            "line {}: unknown condition {}",
+            i + 1,
+            condition
+        ))?;
+        let frames = frames
+            .parse::<u64>()
+            .map_err(|e| format!("line {}: {}", i + 1, e))?;
+
+        tests.push(TestRom {
+            rom: base.join(rom),
+            condition,
+            frames,
+            reference: reference.map(|r| base.join(r)),
+        });
+    }
+
+    Ok(tests)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_mooneye, diff, parse_manifest, Condition};
+    use crate::cpu::Registers;
+    use std::path::Path;
+
+    #[test]
+    fn test_parse_manifest() {
+        let tests = parse_manifest(
+            "# comment\npicture.gb frames 10 picture.png\n\ncpu_instrs.gb blargg 4000\n",
+            Path::new("roms"),
+        )
+        .unwrap();
+
+        assert_eq!(tests.len(), 2);
+        assert_eq!(tests[0].rom, Path::new("roms/picture.gb"));
+        assert_eq!(
+            tests[0].reference.as_deref(),
+            Some(Path::new("roms/picture.png"))
+        );
+        assert_eq!(tests[1].condition, Condition::Blargg);
+        assert_eq!(tests[1].frames, 4000);
+
+        assert!(parse_manifest("picture.gb frames", Path::new("")).is_err());
+        assert!(parse_manifest("picture.gb unknown 10", Path::new("")).is_err());
+    }
+
+    #[test]
+    fn test_mooneye() {
+        let mut registers = Registers {
+            b: 3,
+            c: 5,
+            d: 8,
+            e: 13,
+            h: 21,
+            l: 34,
+            ..Registers::default()
+        };
+        assert!(check_mooneye(&registers).is_ok());
+
+        registers.l = 0;
+        assert!(check_mooneye(&registers).is_err());
+    }
+
+    #[test]
+    fn test_diff() {
+        let (different, picture) = diff(&[0xFF000000, 0xFFFFFFFF], &[0xFF000000, 0xFF000000]);
+        assert_eq!(different, 1);
+        assert_eq!(picture, vec![0xFF7F7F7F, 0xFFFF0000]);
+    }
+}
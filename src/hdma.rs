@@ -0,0 +1,115 @@
+// CGB VRAM DMA Transfers (HDMA1-HDMA5)
+// ref. https://gbdev.io/pandocs/CGB_Registers.html#lcd-vram-dma-transfers
+//
+// General Purpose DMA copies everything at once while the CPU is stalled.
+// HBlank DMA copies 16 bytes at the start of every HBlank.
+
+const BLOCK_SIZE: u16 = 0x10;
+
+#[derive(Default)]
+pub struct Hdma {
+    // HDMA1, HDMA2 - Source, lower 4 bits are ignored
+    source: u16,
+    // HDMA3, HDMA4 - Destination in VRAM, only bits 12-4 are used
+    destination: u16,
+    // blocks of 16 bytes left
+    blocks: u8,
+    // HBlank DMA in progress
+    hblank: bool,
+}
+
+impl Hdma {
+    /// Returns the number of blocks to copy right away (General Purpose DMA).
+    pub fn write(&mut self, address: u16, value: u8) -> u8 {
+        match address {
+            0xff51 => self.source = (self.source & 0x00ff) | ((value as u16) << 8),
+            0xff52 => self.source = (self.source & 0xff00) | (value & 0xf0) as u16,
+            0xff53 => {
+                self.destination = (self.destination & 0x00ff) | (((value & 0x1f) as u16) << 8)
+            }
+            0xff54 => self.destination = (self.destination & 0xff00) | (value & 0xf0) as u16,
+            // HDMA5
+            // Bit 7   Transfer Mode (0=General Purpose DMA, 1=HBlank DMA)
+            // Bit 6-0 Transfer Length divided by 10h, minus 1
+            0xff55 => {
+                if self.hblank && value & 0x80 == 0 {
+                    // cancel the HBlank DMA
+                    self.hblank = false;
+                    return 0;
+                }
+
+                self.blocks = (value & 0x7f) + 1;
+                if value & 0x80 > 0 {
+                    self.hblank = true;
+                } else {
+                    return self.blocks;
+                }
+            }
+            _ => panic!("unexpected address #{:X}", address),
+        }
+
+        0
+    }
+
+    pub fn read(&self, address: u16) -> u8 {
+        match address {
+            // write only
+            0xff51..=0xff54 => 0xff,
+            // Bit 7 is 0 while HBlank DMA is active, 1 when it's done or cancelled.
+            // Bit 6-0 are the remaining length (0x7F once the transfer is complete)
+            0xff55 => {
+                let remaining = self.blocks.wrapping_sub(1) & 0x7f;
+                match self.hblank {
+                    true => remaining,
+                    false => 0x80 | remaining,
+                }
+            }
+            _ => panic!("unexpected address #{:X}", address),
+        }
+    }
+
+    pub fn is_hblank_active(&self) -> bool {
+        self.hblank
+    }
+
+    /// Returns (source, destination) of the next 16 bytes to copy.
+    pub fn next_block(&mut self) -> (u16, u16) {
+        let block = (self.source, 0x8000 | self.destination);
+
+        self.source = self.source.wrapping_add(BLOCK_SIZE);
+        self.destination = (self.destination + BLOCK_SIZE) & 0x1ff0;
+        self.blocks -= 1;
+        if self.blocks == 0 {
+            self.hblank = false;
+        }
+
+        block
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Hdma;
+
+    #[test]
+    fn test_hblank_dma() {
+        let mut hdma = Hdma::default();
+        hdma.write(0xff51, 0xc1);
+        hdma.write(0xff52, 0x2f);
+        hdma.write(0xff53, 0xff);
+        hdma.write(0xff54, 0xf0);
+        // 3 blocks on HBlank
+        assert_eq!(hdma.write(0xff55, 0x82), 0);
+        assert_eq!(hdma.read(0xff55), 0x02);
+
+        assert_eq!(hdma.next_block(), (0xc120, 0x9ff0));
+        // destination wraps inside VRAM
+        assert_eq!(hdma.next_block(), (0xc130, 0x8000));
+        assert_eq!(hdma.read(0xff55), 0x00);
+
+        // cancel
+        hdma.write(0xff55, 0x00);
+        assert!(!hdma.is_hblank_active());
+        assert_eq!(hdma.read(0xff55), 0x80);
+    }
+}
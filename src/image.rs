@@ -0,0 +1,95 @@
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::path::Path;
+
+// Pixels are 0xAARRGGBB, the same layout as `Ppu::buffer`.
+
+/// Writes `buffer` (`width` x `height` pixels) to `path` as an RGB PNG file.
+pub fn write_png<P: AsRef<Path>>(
+    path: P,
+    width: usize,
+    height: usize,
+    buffer: &[u32],
+) -> io::Result<()> {
+    let file = File::create(path)?;
+
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut data = Vec::with_capacity(width * height * 3);
+    for pixel in buffer.iter() {
+        data.push((pixel >> 16) as u8);
+        data.push((pixel >> 8) as u8);
+        data.push(*pixel as u8);
+    }
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&data)?;
+
+    Ok(())
+}
+
+/// Reads a PNG file into 0xAARRGGBB pixels. Returns `(width, height, buffer)`.
+pub fn read_png<P: AsRef<Path>>(path: P) -> io::Result<(usize, usize, Vec<u32>)> {
+    let mut decoder = png::Decoder::new(File::open(path)?);
+    // palette and low bit depth images are expanded to 8 bits per channel
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+
+    let mut reader = decoder.read_info()?;
+    let mut data = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut data)?;
+
+    let channels = info.color_type.samples();
+    let buffer = data[..info.buffer_size()]
+        .chunks_exact(channels)
+        .map(|p| match info.color_type {
+            png::ColorType::Grayscale | png::ColorType::GrayscaleAlpha => {
+                let v = p[0] as u32;
+                0xFF000000 | (v << 16) | (v << 8) | v
+            }
+            _ => 0xFF000000 | ((p[0] as u32) << 16) | ((p[1] as u32) << 8) | p[2] as u32,
+        })
+        .collect();
+
+    Ok((info.width as usize, info.height as usize, buffer))
+}
+
+/// Enlarges `buffer` by an integer `factor` (nearest neighbor).
+pub fn scale(width: usize, height: usize, buffer: &[u32], factor: usize) -> Vec<u32> {
+    let mut scaled = Vec::with_capacity(width * height * factor * factor);
+    for y in 0..height * factor {
+        let row = &buffer[(y / factor) * width..(y / factor + 1) * width];
+        for x in 0..width * factor {
+            scaled.push(row[x / factor]);
+        }
+    }
+
+    scaled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_png, scale, write_png};
+
+    #[test]
+    fn test_scale() {
+        let buffer = [1, 2, 3, 4];
+        assert_eq!(
+            scale(2, 2, &buffer, 2),
+            vec![1, 1, 2, 2, 1, 1, 2, 2, 3, 3, 4, 4, 3, 3, 4, 4]
+        );
+    }
+
+    #[test]
+    fn test_write_read_png() {
+        let path = std::env::temp_dir().join("gbrust_test_image.png");
+        let buffer = [
+            0xFF0F380F, 0xFF306230, 0xFF8BAC0F, 0xFF9BBC0F, 0xFFFFFFFF, 0xFF000000,
+        ];
+        write_png(&path, 3, 2, &buffer).unwrap();
+
+        assert_eq!(read_png(&path).unwrap(), (3, 2, buffer.to_vec()));
+    }
+}
@@ -0,0 +1,112 @@
+// ref. https://gbdev.io/pandocs/Joypad_Input.html
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Button {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+}
+
+impl Button {
+    pub const ALL: [Button; 8] = [
+        Button::Right,
+        Button::Left,
+        Button::Up,
+        Button::Down,
+        Button::A,
+        Button::B,
+        Button::Select,
+        Button::Start,
+    ];
+
+    // bit of the button in `Joypad::pressed`
+    // Bit 0-3: Right, Left, Up, Down (P14), Bit 4-7: A, B, Select, Start (P15)
+    fn mask(self) -> u8 {
+        match self {
+            Button::Right => 0x01,
+            Button::Left => 0x02,
+            Button::Up => 0x04,
+            Button::Down => 0x08,
+            Button::A => 0x10,
+            Button::B => 0x20,
+            Button::Select => 0x40,
+            Button::Start => 0x80,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Joypad {
+    // 1 = pressed
+    pressed: u8,
+    pub irq: bool,
+}
+
+impl Joypad {
+    pub fn set(&mut self, button: Button, pressed: bool) {
+        if pressed {
+            // Joypad interrupt on a high to low transition of P10-P13
+            if self.pressed & button.mask() == 0 {
+                self.irq = true;
+            }
+            self.pressed |= button.mask();
+        } else {
+            self.pressed &= !button.mask();
+        }
+    }
+
+    pub fn is_pressed(&self, button: Button) -> bool {
+        self.pressed & button.mask() > 0
+    }
+
+    /// All the buttons, bit 0-7: Right, Left, Up, Down, A, B, Select, Start (1 = pressed).
+    pub fn state(&self) -> u8 {
+        self.pressed
+    }
+
+    pub fn set_state(&mut self, state: u8) {
+        for button in Button::ALL.iter() {
+            self.set(*button, state & button.mask() > 0);
+        }
+    }
+
+    /// Bit 0-3 of P1 for the select bits 4-5 (0=Select), 0 = pressed.
+    pub fn read(&self, select: u8) -> u8 {
+        let mut pressed = 0;
+        // P14 - Select Direction Keys
+        if select & 0x10 == 0 {
+            pressed |= self.pressed & 0x0f;
+        }
+        // P15 - Select Button Keys
+        if select & 0x20 == 0 {
+            pressed |= self.pressed >> 4;
+        }
+
+        !pressed & 0x0f
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Button, Joypad};
+
+    #[test]
+    fn test_read() {
+        let mut joypad = Joypad::default();
+        joypad.set(Button::Down, true);
+        joypad.set(Button::A, true);
+        assert!(joypad.irq);
+
+        assert_eq!(joypad.read(0x30), 0x0f);
+        assert_eq!(joypad.read(0x20), 0x07);
+        assert_eq!(joypad.read(0x10), 0x0e);
+
+        joypad.set(Button::Down, false);
+        assert_eq!(joypad.read(0x20), 0x0f);
+    }
+}
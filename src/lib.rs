@@ -1,5 +1,23 @@
 pub mod catridge;
+pub mod cheat;
 pub mod cpu;
+pub mod dma;
+pub mod emulator;
+pub mod harness;
+pub mod hdma;
+pub mod image;
+pub mod joypad;
 pub mod mmu;
+pub mod movie;
+pub mod pacing;
+pub mod palette;
+pub mod patch;
 pub mod ppu;
+pub mod rom;
+pub mod serial;
+pub mod sgb;
+#[cfg(feature = "terminal")]
+pub mod terminal;
 pub mod timer;
+#[cfg(feature = "window")]
+pub mod window;
@@ -1,25 +1,133 @@
-mod catridge;
-mod cpu;
-mod mmu;
-mod ppu;
-mod timer;
+use gbrust::cheat::{Cheat, Cheats};
+use gbrust::emulator::{Emulator, Frontend};
+use gbrust::movie::{Movie, Recorder};
+use gbrust::palette::{Palette, PRESETS};
+use gbrust::rom;
+use gbrust::serial::printer::Printer;
+use gbrust::serial::tcp::TcpLink;
+#[cfg(feature = "terminal")]
+use gbrust::terminal::{ColorMode, TerminalFrontend};
+#[cfg(feature = "window")]
+use gbrust::window::WindowFrontend;
 
 use std::env;
-use std::thread;
-use std::time;
-
-extern crate minifb;
-use minifb::{Window, WindowOptions};
+use std::fs;
+use std::path::Path;
+use std::process;
 
 extern crate getopts;
 use getopts::Options;
 
+fn fail(error: &str) -> ! {
+    eprintln!("{}", error);
+    process::exit(1);
+}
+
 fn main() {
     let args: Vec<String> = env::args().collect();
 
     let mut opts = Options::new();
     opts.optopt("b", "bootrom-file", "set the bootrom file path", "");
     opts.reqopt("f", "rom file", "set the rom file apth", "");
+    opts.optopt(
+        "",
+        "link-listen",
+        "wait for a link cable connection on the port of localhost, or on ADDRESS for LAN play",
+        "[ADDRESS:]PORT",
+    );
+    opts.optopt(
+        "",
+        "link-connect",
+        "connect the link cable to another gbrust",
+        "HOST:PORT",
+    );
+    opts.optopt(
+        "",
+        "printer",
+        "attach a Game Boy Printer writing PNG files to the directory",
+        "DIR",
+    );
+    opts.optopt("", "scale", "window size multiplier (default 2)", "N");
+    opts.optopt(
+        "",
+        "screenshot-scale",
+        "size multiplier of the screenshots saved with F12 (default 1)",
+        "N",
+    );
+    opts.optopt(
+        "",
+        "fast-forward",
+        "speed multiplier while fast-forwarding (Tab), or uncapped (default 4)",
+        "SPEED",
+    );
+    opts.optopt(
+        "",
+        "slow-motion",
+        "speed multiplier in slow-motion (`) (default 0.5)",
+        "SPEED",
+    );
+    opts.optopt(
+        "",
+        "palette",
+        "colors of monochrome games: green, grey or high-contrast (P cycles palettes)",
+        "NAME",
+    );
+    opts.optflag(
+        "",
+        "colorize",
+        "colorize monochrome games like the CGB boot ROM",
+    );
+    opts.optopt(
+        "",
+        "colorize-keys",
+        "colorize monochrome games with the palette of a CGB button combination",
+        "COMBO",
+    );
+    opts.optopt(
+        "",
+        "palette-file",
+        "colorize monochrome games with the palette in the file",
+        "FILE",
+    );
+    opts.optmulti(
+        "",
+        "cheat",
+        "enable a Game Genie (ABC-DEF-GHI) or GameShark (ABCDEFGH) code, F1-F9 toggle cheats",
+        "CODE",
+    );
+    opts.optopt("", "cheat-file", "enable the cheats of the file", "FILE");
+    opts.optopt("", "play", "play the input of a movie file", "FILE");
+    opts.optopt(
+        "",
+        "play-frames",
+        "play only the first frames of the movie, then take the keyboard input",
+        "N",
+    );
+    opts.optopt(
+        "",
+        "record",
+        "record the input to a movie file (the played input included)",
+        "FILE",
+    );
+    opts.optopt(
+        "",
+        "rerecord",
+        "play a movie file (see --play-frames), then record the input after the played frames",
+        "FILE",
+    );
+    opts.optopt(
+        "",
+        "patch",
+        "apply an IPS, UPS or BPS patch (default: the patch next to the ROM with the same name)",
+        "FILE",
+    );
+    opts.optflag("", "terminal", "draw in the terminal instead of a window");
+    opts.optopt(
+        "",
+        "colors",
+        "terminal colors, detected from COLORTERM by default",
+        "truecolor|256",
+    );
     let matches = match opts.parse(&args[1..]) {
         Ok(m) => m,
         Err(f) => {
@@ -29,45 +137,194 @@ fn main() {
 
     let rom_file = matches.opt_str("f").unwrap();
 
-    let mut cpu = match matches.opt_present("b") {
-        true => {
-            let bootrom_file = matches.opt_str("b").unwrap();
-            cpu::Cpu::new_with_boot_rom(&bootrom_file, &rom_file)
-        }
-        false => cpu::Cpu::new(&rom_file),
+    let parse_scale = |name: &str, default: usize| match matches.opt_str(name) {
+        Some(scale) => match scale.parse::<usize>() {
+            Ok(scale) if scale > 0 => scale,
+            _ => panic!("invalid {} {}", name, scale),
+        },
+        None => default,
     };
+    let scale = parse_scale("scale", 2);
+    let screenshot_scale = parse_scale("screenshot-scale", 1);
 
-    let mut window = Window::new("GameBoy Emulator", 320, 288, WindowOptions::default())
-        .unwrap_or_else(|e| {
-            panic!("{}", e);
-        });
-
-    // thread::sleep(time::Duration::from_secs(10));
+    let parse_speed = |name: &str| {
+        matches
+            .opt_str(name)
+            .map(|speed| match speed.parse::<f64>() {
+                Ok(speed) if speed > 0.0 => speed,
+                _ => panic!("invalid {} {}", name, speed),
+            })
+    };
 
-    loop {
-        let now = time::Instant::now();
+    let patch = matches.opt_str("patch");
+    let rom = rom::load_with_patch(&rom_file, patch.as_deref().map(Path::new))
+        .unwrap_or_else(|e| fail(&format!("failed to load {}: {}", rom_file, e)));
+    let boot_rom = matches.opt_str("b").map(|path| {
+        fs::read(&path).unwrap_or_else(|e| fail(&format!("failed to load {}: {}", path, e)))
+    });
+    let mut emulator = Emulator::from_rom(boot_rom.as_deref(), &rom)
+        .unwrap_or_else(|e| fail(&format!("failed to load {}: {}", rom_file, e)));
+    let cpu = &mut emulator.cpu;
 
-        // 1 frame
+    // palettes cycled with the P key, the selected one first
+    // CGB games use their own palettes
+    let mut palettes = vec![];
+    if !cpu.mmu.ppu.is_cgb() {
+        if let Some(path) = matches.opt_str("palette-file") {
+            let palette =
+                Palette::load(&path).unwrap_or_else(|e| panic!("failed to load {}: {}", path, e));
+            palettes.push(palette);
+        } else if let Some(combination) = matches.opt_str("colorize-keys") {
+            let palette = Palette::manual(&combination)
+                .unwrap_or_else(|| panic!("unknown button combination {}", combination));
+            palettes.push(palette);
+        } else if matches.opt_present("colorize") {
+            palettes.push(cpu.mmu.compatibility_palette());
+        } else if let Some(name) = matches.opt_str("palette") {
+            let palette =
+                Palette::preset(&name).unwrap_or_else(|| panic!("unknown palette {}", name));
+            palettes.push(palette);
+        }
 
-        // https://mgba-emu.github.io/gbdoc/
-        // > One frame takes 70224 cycles
-        // Timing is divided into 154 lines, ~~ Each line takes 456 cycles.
-        let mut elapsed_tick: u32 = 0;
-        while elapsed_tick < 456 * 154 {
-            elapsed_tick += cpu.step() as u32;
+        for (_, palette) in PRESETS.iter() {
+            if !palettes.contains(palette) {
+                palettes.push(*palette);
+            }
         }
+    }
+    emulator.set_palettes(palettes);
 
-        let buffer = &cpu.mmu.ppu.buffer;
+    match matches.opt_str("fast-forward").as_deref() {
+        Some("uncapped") => emulator.pacer.fast_forward = None,
+        Some(_) => emulator.pacer.fast_forward = parse_speed("fast-forward"),
+        None => {}
+    }
+    if let Some(speed) = parse_speed("slow-motion") {
+        emulator.pacer.slow_motion = speed;
+    }
 
-        window
-            .update_with_buffer(buffer, ppu::WIDTH, ppu::HEIGHT)
-            .unwrap();
+    let mut cheats = match matches.opt_str("cheat-file") {
+        Some(path) => {
+            Cheats::load(&path).unwrap_or_else(|e| panic!("failed to load {}: {}", path, e))
+        }
+        None => Cheats::default(),
+    };
+    for code in matches.opt_strs("cheat") {
+        cheats.add(Cheat::parse(&code).unwrap_or_else(|e| panic!("{}", e)));
+    }
+    emulator.cpu.mmu.cheats = cheats;
 
-        let wait = time::Duration::from_micros(1000000 / 60);
-        let elapsed = now.elapsed();
+    let load_movie = |path: &str| {
+        let mut movie =
+            Movie::load(path).unwrap_or_else(|e| panic!("failed to load {}: {}", path, e));
+        if let Some(frames) = matches.opt_str("play-frames") {
+            let frames = frames
+                .parse::<usize>()
+                .unwrap_or_else(|e| panic!("invalid play-frames {}: {}", frames, e));
+            movie.frames.truncate(frames);
+        }
+        movie
+    };
 
-        if wait > elapsed {
-            thread::sleep(wait - elapsed);
+    if let Some(path) = matches.opt_str("rerecord") {
+        if matches.opt_present("play") || matches.opt_present("record") {
+            panic!("--rerecord can't be used with --play or --record");
         }
+        let movie = load_movie(&path);
+        let recorder = Recorder::append(&path, movie.frames.len())
+            .unwrap_or_else(|e| panic!("failed to open {}: {}", path, e));
+        emulator
+            .rerecord_movie(movie, recorder)
+            .unwrap_or_else(|e| panic!("{}: {}", path, e));
+    }
+    // the movie is read before --record overwrites it when it's the same file
+    if let Some(path) = matches.opt_str("play") {
+        let movie = load_movie(&path);
+        emulator
+            .play_movie(movie)
+            .unwrap_or_else(|e| panic!("{}: {}", path, e));
     }
+    if let Some(path) = matches.opt_str("record") {
+        let movie = Movie::new(emulator.cpu.mmu.rom());
+        let recorder = Recorder::create(&path, &movie)
+            .unwrap_or_else(|e| panic!("failed to create {}: {}", path, e));
+        emulator.record_movie(recorder);
+    }
+    let cpu = &mut emulator.cpu;
+
+    if let Some(address) = matches.opt_str("link-listen") {
+        // a port alone only accepts connections from this machine
+        let address = match address.parse::<u16>() {
+            Ok(port) => format!("127.0.0.1:{}", port),
+            Err(_) => address,
+        };
+        let link = TcpLink::listen(&address).unwrap_or_else(|e| panic!("{}", e));
+        cpu.mmu.serial.connect(Box::new(link));
+    } else if let Some(address) = matches.opt_str("link-connect") {
+        let link = TcpLink::connect(&address).unwrap_or_else(|e| panic!("{}", e));
+        cpu.mmu.serial.connect(Box::new(link));
+    } else if let Some(directory) = matches.opt_str("printer") {
+        cpu.mmu.serial.connect(Box::new(Printer::new(directory)));
+    }
+
+    let mut frontend = create_frontend(&matches, &emulator, scale, screenshot_scale);
+    emulator.run(frontend.as_mut());
+}
+
+#[cfg(feature = "terminal")]
+fn terminal_frontend(matches: &getopts::Matches, screenshot_scale: usize) -> Box<dyn Frontend> {
+    let colors = match matches.opt_str("colors").as_deref() {
+        Some("truecolor") => ColorMode::TrueColor,
+        Some("256") => ColorMode::Ansi256,
+        Some(colors) => panic!("unknown colors {}", colors),
+        None => ColorMode::detect(),
+    };
+
+    let frontend = TerminalFrontend::new(colors, screenshot_scale).unwrap_or_else(|e| {
+        panic!("{}", e);
+    });
+    Box::new(frontend)
+}
+
+#[cfg(not(feature = "terminal"))]
+fn terminal_frontend(_matches: &getopts::Matches, _screenshot_scale: usize) -> Box<dyn Frontend> {
+    panic!("built without the terminal frontend");
+}
+
+// the terminal is used when asked or when there's no window
+fn use_terminal(matches: &getopts::Matches) -> bool {
+    matches.opt_present("terminal") || !cfg!(feature = "window")
+}
+
+fn create_frontend(
+    matches: &getopts::Matches,
+    emulator: &Emulator,
+    scale: usize,
+    screenshot_scale: usize,
+) -> Box<dyn Frontend> {
+    if use_terminal(matches) {
+        terminal_frontend(matches, screenshot_scale)
+    } else {
+        window_frontend(emulator, scale, screenshot_scale)
+    }
+}
+
+#[cfg(feature = "window")]
+fn window_frontend(
+    emulator: &Emulator,
+    scale: usize,
+    screenshot_scale: usize,
+) -> Box<dyn Frontend> {
+    // SGB games are shown with their border
+    let (_, width, height) = emulator.screen();
+    Box::new(WindowFrontend::new(width, height, scale, screenshot_scale))
+}
+
+#[cfg(not(feature = "window"))]
+fn window_frontend(
+    _emulator: &Emulator,
+    _scale: usize,
+    _screenshot_scale: usize,
+) -> Box<dyn Frontend> {
+    panic!("built without the window frontend");
 }
@@ -2,77 +2,175 @@ use std::fs::File;
 use std::io::Read;
 
 use crate::catridge::Catridge;
+use crate::cheat::Cheats;
+use crate::dma::Dma;
+use crate::hdma::Hdma;
+use crate::joypad::Joypad;
+use crate::palette::Palette;
 use crate::ppu::Ppu;
+use crate::rom;
+use crate::serial::Serial;
+use crate::sgb::{self, Sgb};
 use crate::timer::Timer;
 
+// Bits of the sound registers ($FF10-$FF3F) which always read as 1
+// ref. https://gbdev.gg8.se/wiki/articles/Gameboy_sound_hardware#Register_Reading
+const AUDIO_READ_MASK: [u8; 0x30] = [
+    0x80, 0x3f, 0x00, 0xff, 0xbf, // NR10-NR14
+    0xff, 0x3f, 0x00, 0xff, 0xbf, // NR20-NR24
+    0x7f, 0xff, 0x9f, 0xff, 0xbf, // NR30-NR34
+    0xff, 0xff, 0x00, 0x00, 0xbf, // NR40-NR44
+    0x00, 0x00, 0x70, // NR50-NR52
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, // $FF27-$FF2F
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Wave RAM
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+// 0143 - CGB Flag
+// 80h - Game supports CGB functions, but works on old gameboys also.
+// C0h - Game works on CGB only
+fn is_cgb(catridge: &Catridge) -> bool {
+    catridge.read(0x0143) & 0x80 > 0
+}
+
+// CGB games run in CGB mode even when they support the SGB
+fn new_sgb(catridge: &Catridge, cgb: bool) -> Option<Sgb> {
+    match !cgb && sgb::is_sgb(catridge.read(0x0146), catridge.read(0x014b)) {
+        true => Some(Sgb::default()),
+        false => None,
+    }
+}
+
 pub struct Mmu {
     boot_rom: Vec<u8>, // 0x0000 to 0x00FF
     catridge: Catridge,
-    ram: [u8; 65536], // 0x0000 to 0xffff
+    /// Work RAM, 8 banks of 4KB on CGB
+    wram: [u8; 0x8000], // 0xC000 to 0xDFFF
     /// High RAM
     hram: [u8; 0x7f],
     pub ppu: Ppu,
     pub timer: Timer,
+    dma: Dma,
+    hdma: Hdma,
+    // CPU clocks stalled by HDMA, not yet consumed by the CPU
+    stalled_clocks: usize,
+    // $FF00 - P1/JOYP - Joypad, only the select bits (4-5) are writable
+    p1: u8,
+    pub joypad: Joypad,
+    // $FF10-$FF3F - Sound registers and Wave RAM
+    audio: [u8; 0x30],
+    cgb: bool,
+    // SVBK - CGB Mode Only - WRAM Bank
+    svbk: u8,
+    // KEY1 - CGB Mode Only - Prepare Speed Switch
+    // Bit 0: Prepare Speed Switch (0=No, 1=Prepare)
+    key1: u8,
+    double_speed: bool,
+    // the PPU entered VBlank since the last `take_vblank`
+    vblank: bool,
     pub boot_rom_enabled: bool,
     pub interrupt_flag: u8,
     pub interrupt_enable: u8,
 
-    pub serial_port: String,
+    pub serial: Serial,
+    pub sgb: Option<Sgb>,
+    pub cheats: Cheats,
 }
 
 impl Mmu {
     pub fn new_with_boot_rom(boot_rom_name: &str, rom_name: &str) -> Self {
-        let mut file = File::open(rom_name).unwrap();
-        let mut rom = Vec::<u8>::new();
-
-        file.read_to_end(&mut rom).unwrap();
-
         let mut boot_rom_file = File::open(boot_rom_name).unwrap();
         let mut boot_rom = Vec::<u8>::new();
         boot_rom_file.read_to_end(&mut boot_rom).unwrap();
 
-        Mmu {
-            boot_rom,
-            catridge: Catridge::new(rom_name),
-            ram: [0; 65536],
-            hram: [0; 0x7f],
-            ppu: Ppu::default(),
-            timer: Timer::default(),
-            boot_rom_enabled: true,
-            interrupt_flag: 0,
-            interrupt_enable: 0,
-            serial_port: "".to_string(),
-        }
+        Mmu::from_rom(Some(boot_rom), rom::load(rom_name).unwrap())
+            .unwrap_or_else(|e| panic!("{}: {}", rom_name, e))
     }
 
     pub fn new(rom_name: &str) -> Self {
-        let mut file = File::open(rom_name).unwrap();
-        let mut rom = Vec::<u8>::new();
-
-        file.read_to_end(&mut rom).unwrap();
+        Mmu::from_rom(None, rom::load(rom_name).unwrap())
+            .unwrap_or_else(|e| panic!("{}: {}", rom_name, e))
+    }
 
-        Mmu {
-            boot_rom: vec![],
-            catridge: Catridge::new(rom_name),
-            ram: [0; 65536],
+    /// `rom` is the unpacked ROM (see `rom::unpack`).
+    pub fn from_rom(boot_rom: Option<Vec<u8>>, rom: Vec<u8>) -> Result<Self, String> {
+        let catridge = Catridge::from_bytes(rom)?;
+        let cgb = is_cgb(&catridge);
+        let sgb = new_sgb(&catridge, cgb);
+
+        Ok(Mmu {
+            boot_rom_enabled: boot_rom.is_some(),
+            boot_rom: boot_rom.unwrap_or_default(),
+            catridge,
+            wram: [0; 0x8000],
             hram: [0; 0x7f],
-            ppu: Ppu::default(),
+            ppu: Ppu::new(cgb),
             timer: Timer::default(),
-            boot_rom_enabled: false,
+            dma: Dma::default(),
+            hdma: Hdma::default(),
+            stalled_clocks: 0,
+            p1: 0x30,
+            joypad: Joypad::default(),
+            audio: [0; 0x30],
+            cgb,
+            svbk: 0,
+            key1: 0,
+            double_speed: false,
+            vblank: false,
             interrupt_flag: 0,
             interrupt_enable: 0,
-            serial_port: "".to_string(),
-        }
+            serial: Serial::new_with_model(cgb),
+            sgb,
+            cheats: Cheats::default(),
+        })
     }
 
     pub fn step(&mut self, clocks: usize) {
-        self.ppu.step(clocks);
+        // OAM DMA transfers 1 byte per M-cycle
+        for _ in 0..(clocks / 4) {
+            if let Some((source, destination)) = self.dma.tick() {
+                let value = self.read_bus(source);
+                self.dma.value = value;
+                self.ppu.write(destination, value);
+            }
+        }
+
+        // In double speed mode the CPU, timer, serial and OAM DMA run twice as fast,
+        // the PPU and the RTC of the cartridge keep running at normal speed.
+        match self.double_speed {
+            true => {
+                self.ppu.step(clocks / 2);
+                self.catridge.step(clocks / 2);
+            }
+            false => {
+                self.ppu.step(clocks);
+                self.catridge.step(clocks);
+            }
+        }
         self.timer.step(clocks);
+        self.serial.step(clocks);
+
+        // HBlank DMA copies 16 bytes when the PPU enters HBlank
+        if self.ppu.hblank {
+            self.ppu.hblank = false;
+            if self.hdma.is_hblank_active() {
+                self.hdma_transfer(1);
+            }
+        }
 
         // V-Blank interrupt Request
         if self.ppu.vblank {
+            if let Some(sgb) = self.sgb.as_mut() {
+                sgb.vblank(&self.ppu);
+            }
             self.interrupt_flag |= 0x01;
             self.ppu.vblank = false;
+            self.vblank = true;
+
+            // GameShark codes
+            for (address, value) in self.cheats.ram_writes() {
+                self.write_byte(address, value);
+            }
         }
 
         // Timer interrup Request
@@ -80,9 +178,100 @@ impl Mmu {
             self.interrupt_flag |= 0x04;
             self.timer.irq = false;
         }
+
+        // Serial interrupt Request
+        if self.serial.irq {
+            self.interrupt_flag |= 0x08;
+            self.serial.irq = false;
+        }
+
+        // Joypad interrupt Request
+        if self.joypad.irq {
+            self.interrupt_flag |= 0x10;
+            self.joypad.irq = false;
+        }
+    }
+
+    /// Palette the CGB boot ROM picks for this game when it's a monochrome game.
+    pub fn compatibility_palette(&self) -> Palette {
+        let header: Vec<u8> = (0x0134..=0x014b).map(|a| self.catridge.read(a)).collect();
+        Palette::compatibility(&header)
+    }
+
+    /// Tilt of the cartridge, for MBC7 games.
+    pub fn set_tilt(&mut self, x: f32, y: f32) {
+        self.catridge.set_tilt(x, y);
+    }
+
+    /// The whole ROM of the cartridge.
+    pub fn rom(&self) -> &[u8] {
+        self.catridge.rom()
+    }
+
+    /// Whether the PPU entered VBlank since the last call.
+    pub fn take_vblank(&mut self) -> bool {
+        let vblank = self.vblank;
+        self.vblank = false;
+
+        vblank
+    }
+
+    /// Clocks the CPU has to wait for HDMA to finish. Resets the counter.
+    pub fn take_stalled_clocks(&mut self) -> usize {
+        let clocks = self.stalled_clocks;
+        self.stalled_clocks = 0;
+
+        clocks
+    }
+
+    fn hdma_transfer(&mut self, blocks: u8) {
+        for _ in 0..blocks {
+            let (source, destination) = self.hdma.next_block();
+            for i in 0..0x10 {
+                let value = self.read_bus(source.wrapping_add(i));
+                self.ppu.write(destination + i, value);
+            }
+
+            // 16 bytes take 8 M-cycles at normal speed, twice as many CPU clocks in double speed
+            self.stalled_clocks += match self.double_speed {
+                true => 64,
+                false => 32,
+            };
+        }
+    }
+
+    pub fn is_double_speed(&self) -> bool {
+        self.double_speed
+    }
+
+    /// STOP instruction, switches the CPU speed when it has been prepared with KEY1.
+    pub fn stop(&mut self) {
+        if self.cgb && self.key1 & 0x01 > 0 {
+            self.double_speed = !self.double_speed;
+            self.key1 = 0;
+        }
+    }
+
+    // $C000-$CFFF is always bank 0, $D000-$DFFF is bank 1-7 selected by SVBK (0 selects 1)
+    fn wram_offset(&self, address: u16) -> usize {
+        let offset = (address & 0x1fff) as usize;
+        match offset {
+            0x0000..=0x0fff => offset,
+            _ => {
+                let bank = match self.svbk {
+                    0 => 1,
+                    bank => bank as usize,
+                };
+                bank * 0x1000 + (offset - 0x1000)
+            }
+        }
     }
 
     pub fn write_byte(&mut self, address: u16, value: u8) {
+        if self.dma_conflict(address) {
+            return;
+        }
+
         match address {
             // rom
             0x0000..=0x00ff => {
@@ -95,54 +284,67 @@ impl Mmu {
             }
 
             // PPU
-            // VRAM
-            0x8000..=0x9fff => {
-                self.ppu.write(address, value);
-            }
+            // VRAM, not writable while the PPU is drawing (mode 3)
+            0x8000..=0x9fff if self.ppu.is_vram_accessible() => self.ppu.write(address, value),
 
             // External RAM
             0xa000..=0xbfff => {
                 self.catridge.write(address, value);
             }
 
-            // main ram
-            0xc000..=0xdfff => self.ram[(address - 0xC000) as usize] = value,
-
-            // OAM
-            0xfe00..=0xfe9f => {
-                self.ppu.write(address, value);
+            // main ram, and Echo RAM (mirror of $C000-$DDFF)
+            0xc000..=0xfdff => {
+                let offset = self.wram_offset(address);
+                self.wram[offset] = value;
             }
 
-            // for console
-            0xff01 => {
-                self.serial_port.push(value as char);
-                // print!("{}", value as char);
-            }
+            // OAM, not writable during OAM scan and drawing (mode 2 and 3)
+            0xfe00..=0xfe9f if self.ppu.is_oam_accessible() => self.ppu.write(address, value),
 
-            // I/O Registers
-            0xff40..=0xff45 | 0xff47..=0xff4b => self.ppu.write(address, value),
+            // Not Usable
+            0xfea0..=0xfeff => {}
 
-            // DMA
-            0xff46 => {
-                let xx = (value as u16 & 0x00ff) << 8;
-                for i in 0..0xa0 {
-                    // source
-                    let v = self.read_byte(xx | i);
-                    // dest: oam
-                    self.write_byte(0xfe00 | i, v);
+            // Joypad
+            0xff00 => {
+                self.p1 = value & 0x30;
+                if let Some(sgb) = self.sgb.as_mut() {
+                    sgb.write_p1(value);
                 }
             }
 
+            // Serial
+            0xff01..=0xff02 => self.serial.write_byte(address, value),
+
             // Timer
             0xff04..=0xff07 => self.timer.write_byte(address, value),
 
             // Interrupt Flag
             0xff0f => {
-                self.interrupt_flag = value;
+                self.interrupt_flag = value & 0x1f;
             }
-            // Interrupt Enable
-            0xffff => {
-                self.interrupt_enable = value;
+
+            // Sound
+            0xff10..=0xff3f => self.audio[(address - 0xff10) as usize] = value,
+
+            // I/O Registers
+            0xff40..=0xff45 | 0xff47..=0xff4b => self.ppu.write(address, value),
+
+            // CGB: VRAM bank and color palettes
+            0xff4f | 0xff68..=0xff6b => self.ppu.write(address, value),
+
+            // DMA
+            0xff46 => self.dma.start(value),
+
+            // CGB: Prepare Speed Switch
+            0xff4d if self.cgb => self.key1 = value & 0x01,
+
+            // CGB: WRAM Bank
+            0xff70 if self.cgb => self.svbk = value & 0x07,
+
+            // CGB: VRAM DMA
+            0xff51..=0xff55 if self.cgb => {
+                let blocks = self.hdma.write(address, value);
+                self.hdma_transfer(blocks);
             }
 
             0xff50 => {
@@ -153,24 +355,64 @@ impl Mmu {
             // HRAM
             0xff80..=0xfffe => self.hram[(address & 0x7f) as usize] = value,
 
-            _ => {
-                self.ram[address as usize] = value;
+            // Interrupt Enable
+            0xffff => {
+                self.interrupt_enable = value;
             }
+
+            // unmapped I/O registers, or VRAM/OAM locked by the PPU
+            _ => {}
         }
     }
 
     pub fn read_byte(&self, address: u16) -> u8 {
+        if self.dma_conflict(address) {
+            return match address {
+                // OAM
+                0xfe00..=0xfeff => 0xff,
+                _ => self.dma.value,
+            };
+        }
+
+        match address {
+            // VRAM can't be read while the PPU is drawing (mode 3)
+            0x8000..=0x9fff if !self.ppu.is_vram_accessible() => 0xff,
+            // OAM can't be read during OAM scan and drawing (mode 2 and 3)
+            0xfe00..=0xfeff if !self.ppu.is_oam_accessible() => 0xff,
+            _ => self.read_bus(address),
+        }
+    }
+
+    // While OAM DMA is running the CPU can't use OAM and the bus the DMA reads from.
+    // The external bus (ROM, external RAM, WRAM) and the video bus (VRAM) are separate,
+    // I/O registers and HRAM are always accessible.
+    fn dma_conflict(&self, address: u16) -> bool {
+        if !self.dma.is_active() {
+            return false;
+        }
+
+        let video_bus = |address: u16| (0x8000..=0x9fff).contains(&address);
+
+        match address {
+            0xfe00..=0xfeff => true,
+            0x0000..=0xfdff => video_bus(address) == video_bus(self.dma.source()),
+            _ => false,
+        }
+    }
+
+    fn read_bus(&self, address: u16) -> u8 {
         match address {
             0x0000..=0x00ff => {
                 if self.boot_rom_enabled {
                     return self.boot_rom[address as usize];
                 }
 
-                self.catridge.read(address)
+                self.cheats.patch_rom(address, self.catridge.read(address))
             }
 
             // ROM
-            0x0100..=0x7fff => self.catridge.read(address),
+            // Game Genie codes
+            0x0100..=0x7fff => self.cheats.patch_rom(address, self.catridge.read(address)),
 
             // PPU
             0x8000..=0x9fff => self.ppu.read(address),
@@ -179,26 +421,185 @@ impl Mmu {
             // External RAM
             0xa000..=0xbfff => self.catridge.read(address),
 
-            // main ram
-            0xc000..=0xdfff => self.ram[(address - 0xC000) as usize],
+            // main ram, and Echo RAM (mirror of $C000-$DDFF)
+            0xc000..=0xfdff => self.wram[self.wram_offset(address)],
 
             // OAM
             0xfe00..=0xfe9f => self.ppu.read(address),
 
-            0xff40..=0xff45 | 0xff47..=0xff4b => self.ppu.read(address),
+            // Not Usable, reads 0 on DMG
+            0xfea0..=0xfeff => 0x00,
+
+            // Joypad
+            // Bit 3-0 - Input (0=Pressed)
+            // SGB: the joypad number when neither buttons nor directions are selected
+            0xff00 => match &self.sgb {
+                Some(sgb) if self.p1 == 0x30 => 0xc0 | self.p1 | sgb.joypad_id(),
+                _ => 0xc0 | self.p1 | self.joypad.read(self.p1),
+            },
+
+            // Serial
+            0xff01..=0xff02 => self.serial.read_byte(address),
 
             // Timer
             0xff04..=0xff07 => self.timer.read_byte(address),
 
             // Interrupt Flag
-            0xff0f => self.interrupt_flag,
-            // Interrupt Enable
-            0xffff => self.interrupt_enable,
+            0xff0f => 0xe0 | self.interrupt_flag,
+
+            // Sound
+            0xff10..=0xff3f => {
+                let index = (address - 0xff10) as usize;
+                self.audio[index] | AUDIO_READ_MASK[index]
+            }
+
+            0xff40..=0xff45 | 0xff47..=0xff4b => self.ppu.read(address),
+
+            // CGB: VRAM bank and color palettes
+            0xff4f | 0xff68..=0xff6b => self.ppu.read(address),
+
+            // DMA
+            0xff46 => self.dma.read(),
+
+            // CGB: Bit 7 Current Speed (0=Normal, 1=Double)
+            0xff4d if self.cgb => 0x7e | ((self.double_speed as u8) << 7) | self.key1,
+
+            // CGB: WRAM Bank
+            0xff70 if self.cgb => 0xf8 | self.svbk,
+
+            // CGB: VRAM DMA
+            0xff51..=0xff55 if self.cgb => self.hdma.read(address),
 
             // HRAM
             0xff80..=0xfffe => self.hram[(address & 0x7f) as usize],
 
+            // Interrupt Enable
+            0xffff => self.interrupt_enable,
+
+            // unmapped I/O registers
             _ => 0xff,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Mmu;
+
+    // 32KB ROM without MBC
+    fn mmu() -> Mmu {
+        Mmu::from_rom(None, vec![0; 0x8000]).unwrap()
+    }
+
+    fn cgb_mmu() -> Mmu {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0143] = 0x80;
+        Mmu::from_rom(None, rom).unwrap()
+    }
+
+    #[test]
+    fn test_echo_ram() {
+        let mut mmu = mmu();
+        mmu.write_byte(0xc123, 0x42);
+        assert_eq!(mmu.read_byte(0xe123), 0x42);
+        mmu.write_byte(0xfdff, 0x24);
+        assert_eq!(mmu.read_byte(0xddff), 0x24);
+    }
+
+    #[test]
+    fn test_unusable_area() {
+        let mut mmu = mmu();
+        // LCD off, OAM accessible
+        mmu.write_byte(0xff40, 0x00);
+        mmu.write_byte(0xfea0, 0x12);
+        assert_eq!(mmu.read_byte(0xfea0), 0x00);
+        assert_eq!(mmu.read_byte(0xfeff), 0x00);
+    }
+
+    #[test]
+    fn test_io_read_mask() {
+        let mut mmu = mmu();
+        // IF: bit 5-7 unused
+        mmu.write_byte(0xff0f, 0x01);
+        assert_eq!(mmu.read_byte(0xff0f), 0xe1);
+        // NR10: bit 7 unused
+        mmu.write_byte(0xff10, 0x00);
+        assert_eq!(mmu.read_byte(0xff10), 0x80);
+        // unmapped
+        assert_eq!(mmu.read_byte(0xff03), 0xff);
+    }
+
+    #[test]
+    fn test_vram_lock() {
+        let mut mmu = mmu();
+        mmu.write_byte(0xff40, 0x00);
+        mmu.write_byte(0x8000, 0x12);
+
+        // OAM scan, then drawing
+        mmu.write_byte(0xff40, 0x80);
+        mmu.step(80);
+        assert_eq!(mmu.read_byte(0x8000), 0xff);
+        mmu.write_byte(0x8000, 0x34);
+        assert_eq!(mmu.read_byte(0xfe00), 0xff);
+
+        // HBlank
+        mmu.step(172);
+        assert_eq!(mmu.read_byte(0x8000), 0x12);
+    }
+
+    #[test]
+    fn test_wram_banks() {
+        let mut mmu = cgb_mmu();
+
+        // bank 0 selects bank 1
+        mmu.write_byte(0xff70, 0x00);
+        mmu.write_byte(0xd000, 0x11);
+        mmu.write_byte(0xff70, 0x01);
+        assert_eq!(mmu.read_byte(0xd000), 0x11);
+        assert_eq!(mmu.read_byte(0xff70), 0xf9);
+
+        mmu.write_byte(0xff70, 0x07);
+        assert_eq!(mmu.read_byte(0xd000), 0x00);
+        mmu.write_byte(0xd000, 0x77);
+        // $C000-$CFFF is always bank 0
+        mmu.write_byte(0xc000, 0x22);
+
+        mmu.write_byte(0xff70, 0x02);
+        assert_eq!(mmu.read_byte(0xc000), 0x22);
+        assert_eq!(mmu.read_byte(0xd000), 0x00);
+        mmu.write_byte(0xff70, 0x07);
+        assert_eq!(mmu.read_byte(0xd000), 0x77);
+    }
+
+    #[test]
+    fn test_speed_switch() {
+        let mut mmu = cgb_mmu();
+
+        // STOP without preparing
+        mmu.stop();
+        assert!(!mmu.is_double_speed());
+
+        mmu.write_byte(0xff4d, 0x01);
+        assert_eq!(mmu.read_byte(0xff4d), 0x7f);
+        mmu.stop();
+        assert!(mmu.is_double_speed());
+        assert_eq!(mmu.read_byte(0xff4d), 0xfe);
+
+        // the PPU runs at half the CPU clocks: 80 clocks are 40 dots of OAM scan
+        mmu.step(80);
+        assert_eq!(mmu.read_byte(0x8000), 0x00);
+        mmu.step(80);
+        assert_eq!(mmu.read_byte(0x8000), 0xff);
+
+        // back to normal speed
+        mmu.write_byte(0xff4d, 0x01);
+        mmu.stop();
+        assert!(!mmu.is_double_speed());
+
+        // DMG ignores KEY1
+        let mut dmg = Mmu::from_rom(None, vec![0; 0x8000]).unwrap();
+        dmg.write_byte(0xff4d, 0x01);
+        dmg.stop();
+        assert!(!dmg.is_double_speed());
+    }
+}
@@ -0,0 +1,214 @@
+// Input movies: the joypad state of every frame, played back to reproduce a run exactly.
+//
+// offset  size  (numbers are little endian)
+// 0       4     "GBMV"
+// 4       1     format version (1)
+// 5       2     ROM checksum: sum of the ROM bytes but the global checksum at $014E-$014F
+// 7       1     length of the emulator version
+// 8       n     emulator version (the crate version)
+// 8+n     1     start: 0 = power-on, the only start as the emulator has no save states
+// 9+n           one byte per frame: the joypad state (`Joypad::state`)
+//
+// Frames have a fixed size after the header, so re-recording truncates the file
+// at a frame and appends from there.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::catridge::header::global_checksum;
+
+const MAGIC: &[u8; 4] = b"GBMV";
+const FORMAT_VERSION: u8 = 1;
+
+/// Version of the emulator writing the movies.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Movie {
+    pub rom_checksum: u16,
+    /// Version of the emulator which recorded the movie.
+    pub version: String,
+    /// Joypad state of each frame.
+    pub frames: Vec<u8>,
+}
+
+/// The global checksum computed over the ROM, not the one in the header.
+pub fn rom_checksum(rom: &[u8]) -> u16 {
+    global_checksum(rom)
+}
+
+fn invalid_data(e: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+impl Movie {
+    /// An empty movie of `rom` starting at power-on.
+    pub fn new(rom: &[u8]) -> Self {
+        Movie {
+            rom_checksum: rom_checksum(rom),
+            version: VERSION.to_string(),
+            frames: vec![],
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Movie::parse(&fs::read(path)?).map_err(invalid_data)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_bytes())
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self, String> {
+        let (mut movie, header_len) = Movie::parse_header(data)?;
+        movie.frames = data[header_len..].to_vec();
+
+        Ok(movie)
+    }
+
+    // The movie without frames and the length of the header.
+    fn parse_header(data: &[u8]) -> Result<(Self, usize), String> {
+        let eof = || "unexpected end of the movie".to_string();
+        let bytes = |from: usize, len: usize| data.get(from..from + len).ok_or_else(eof);
+
+        if bytes(0, 4)? != MAGIC {
+            return Err("not a movie".to_string());
+        }
+        let format = bytes(4, 1)?[0];
+        if format != FORMAT_VERSION {
+            return Err(format!("unknown movie format {}", format));
+        }
+
+        let checksum = bytes(5, 2)?;
+        let rom_checksum = u16::from_le_bytes([checksum[0], checksum[1]]);
+
+        let version_len = bytes(7, 1)?[0] as usize;
+        let version = String::from_utf8_lossy(bytes(8, version_len)?).to_string();
+
+        let start = bytes(8 + version_len, 1)?[0];
+        if start != 0 {
+            return Err(format!("unknown start {}, movies start at power-on", start));
+        }
+
+        let movie = Movie {
+            rom_checksum,
+            version,
+            frames: vec![],
+        };
+        Ok((movie, 9 + version_len))
+    }
+
+    fn header(&self) -> Vec<u8> {
+        let mut header = MAGIC.to_vec();
+        header.push(FORMAT_VERSION);
+        header.extend_from_slice(&self.rom_checksum.to_le_bytes());
+        header.push(self.version.len() as u8);
+        header.extend_from_slice(self.version.as_bytes());
+        // power-on
+        header.push(0);
+        header
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = self.header();
+        data.extend_from_slice(&self.frames);
+        data
+    }
+
+    /// Whether the movie can be played on `rom`.
+    pub fn check(&self, rom: &[u8]) -> Result<(), String> {
+        if self.rom_checksum != rom_checksum(rom) {
+            return Err(format!(
+                "the movie was recorded with another ROM (checksum {:04X}, expected {:04X})",
+                self.rom_checksum,
+                rom_checksum(rom)
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes the frames to a movie file as they are run, so a crash keeps the input until then.
+pub struct Recorder {
+    file: File,
+}
+
+impl Recorder {
+    /// Creates the file with the header of `movie` and its frames.
+    pub fn create<P: AsRef<Path>>(path: P, movie: &Movie) -> io::Result<Self> {
+        let mut file = File::create(path)?;
+        file.write_all(&movie.to_bytes())?;
+
+        Ok(Recorder { file })
+    }
+
+    /// Keeps the first `frames` frames of a movie file and records after them (re-recording).
+    pub fn append<P: AsRef<Path>>(path: P, frames: usize) -> io::Result<Self> {
+        let data = fs::read(&path)?;
+        let (_, header_len) = Movie::parse_header(&data).map_err(invalid_data)?;
+        if header_len + frames > data.len() {
+            return Err(invalid_data(format!(
+                "the movie has only {} frames",
+                data.len() - header_len
+            )));
+        }
+
+        let mut file = OpenOptions::new().write(true).open(path)?;
+        file.set_len((header_len + frames) as u64)?;
+        file.seek(SeekFrom::End(0))?;
+
+        Ok(Recorder { file })
+    }
+
+    pub fn record(&mut self, state: u8) -> io::Result<()> {
+        self.file.write_all(&[state])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{rom_checksum, Movie, Recorder};
+
+    #[test]
+    fn test_movie() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0100] = 0x12;
+        rom[0x014e] = 0x34;
+        assert_eq!(rom_checksum(&rom), 0x12);
+
+        let mut movie = Movie::new(&rom);
+        movie.frames = vec![0x00, 0x10, 0x90];
+        assert_eq!(Movie::parse(&movie.to_bytes()), Ok(movie.clone()));
+        assert!(movie.check(&rom).is_ok());
+
+        rom[0x0100] = 0;
+        assert!(movie.check(&rom).is_err());
+
+        // a start from a save state
+        let mut data = movie.to_bytes();
+        data[8 + movie.version.len()] = 1;
+        assert!(Movie::parse(&data).is_err());
+
+        assert!(Movie::parse(b"GBMV").is_err());
+        assert!(Movie::parse(b"not a movie").is_err());
+    }
+
+    #[test]
+    fn test_rerecord() {
+        let path = std::env::temp_dir().join("gbrust_test_movie.gbm");
+        let mut movie = Movie::new(&[0; 0x8000]);
+        movie.frames = vec![1, 2, 3];
+
+        let mut recorder = Recorder::create(&path, &movie).unwrap();
+        recorder.record(4).unwrap();
+        assert_eq!(Movie::load(&path).unwrap().frames, vec![1, 2, 3, 4]);
+
+        let mut recorder = Recorder::append(&path, 2).unwrap();
+        recorder.record(5).unwrap();
+        assert_eq!(Movie::load(&path).unwrap().frames, vec![1, 2, 5]);
+
+        assert!(Recorder::append(&path, 4).is_err());
+    }
+}
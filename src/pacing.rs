@@ -0,0 +1,191 @@
+// Runs the emulation at the speed of the real hardware, or faster / slower.
+//
+// Frames last 70224 clocks at 4194304 Hz, about 59.73 Hz. Deadlines accumulate the
+// emulated time instead of waiting a fixed budget after each frame, so rounding and
+// oversleeping don't drift the speed.
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::emulator::{CLOCK_HZ, FRAME_CLOCKS};
+
+// Behind the deadline by more than this (e.g. the process was suspended),
+// the lost time is dropped instead of running frames as fast as possible to catch up.
+const MAX_LAG: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Speed {
+    Normal,
+    FastForward,
+    SlowMotion,
+}
+
+pub struct Pacer {
+    /// Speed multiplier of fast-forward, None = as fast as possible.
+    pub fast_forward: Option<f64>,
+    /// Speed multiplier of slow-motion.
+    pub slow_motion: f64,
+    speed: Speed,
+    paused: bool,
+    // run a frame while paused
+    advance: bool,
+    // when the last frame should have ended, None after a pause
+    deadline: Option<Instant>,
+}
+
+impl Default for Pacer {
+    fn default() -> Self {
+        Pacer {
+            fast_forward: Some(4.0),
+            slow_motion: 0.5,
+            speed: Speed::Normal,
+            paused: false,
+            advance: false,
+            deadline: None,
+        }
+    }
+}
+
+/// The duration of `clocks` on the real hardware.
+pub fn clocks_duration(clocks: usize) -> Duration {
+    Duration::from_nanos(clocks as u64 * 1_000_000_000 / CLOCK_HZ)
+}
+
+impl Pacer {
+    pub fn speed(&self) -> Speed {
+        self.speed
+    }
+
+    pub fn set_speed(&mut self, speed: Speed) {
+        self.speed = speed;
+    }
+
+    /// Switches between `speed` and the normal speed.
+    pub fn toggle_speed(&mut self, speed: Speed) {
+        self.speed = match self.speed == speed {
+            true => Speed::Normal,
+            false => speed,
+        };
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        self.advance = false;
+    }
+
+    /// Runs one more frame while paused.
+    pub fn advance_frame(&mut self) {
+        if self.paused {
+            self.advance = true;
+        }
+    }
+
+    /// Whether to run the next frame.
+    pub fn should_run(&mut self) -> bool {
+        if !self.paused {
+            return true;
+        }
+
+        let advance = self.advance;
+        self.advance = false;
+        advance
+    }
+
+    // Speed multiplier, None = as fast as possible.
+    fn multiplier(&self) -> Option<f64> {
+        match self.speed {
+            Speed::Normal => Some(1.0),
+            Speed::FastForward => self.fast_forward,
+            Speed::SlowMotion => Some(self.slow_motion),
+        }
+    }
+
+    /// Waits until a frame which lasts `emulated` on the real hardware is due.
+    pub fn wait(&mut self, emulated: Duration) {
+        let now = Instant::now();
+        self.deadline = self.next_deadline(now, emulated);
+
+        if let Some(deadline) = self.deadline {
+            if deadline > now {
+                thread::sleep(deadline - now);
+            }
+        }
+    }
+
+    fn next_deadline(&self, now: Instant, emulated: Duration) -> Option<Instant> {
+        let multiplier = self.multiplier()?;
+
+        // after a pause, the deadlines start over
+        let deadline = self.deadline.unwrap_or(now) + emulated.div_f64(multiplier);
+        if deadline + MAX_LAG < now {
+            return Some(now);
+        }
+
+        Some(deadline)
+    }
+
+    /// Waits for a frame without running it (paused).
+    pub fn idle(&mut self) {
+        self.deadline = None;
+        thread::sleep(clocks_duration(FRAME_CLOCKS));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{clocks_duration, Pacer, Speed};
+    use crate::emulator::FRAME_CLOCKS;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_next_deadline() {
+        let frame = clocks_duration(FRAME_CLOCKS);
+        assert_eq!(frame, Duration::from_nanos(16_742_706));
+
+        let now = Instant::now();
+        let mut pacer = Pacer::default();
+        pacer.deadline = pacer.next_deadline(now, frame);
+        assert_eq!(pacer.deadline, Some(now + frame));
+
+        // accumulated, not from the current time
+        let later = now + frame / 2;
+        assert_eq!(pacer.next_deadline(later, frame), Some(now + frame * 2));
+
+        pacer.set_speed(Speed::FastForward);
+        assert_eq!(
+            pacer.next_deadline(now, frame),
+            Some(now + frame + frame.div_f64(4.0))
+        );
+        pacer.fast_forward = None;
+        assert_eq!(pacer.next_deadline(now, frame), None);
+
+        pacer.set_speed(Speed::SlowMotion);
+        assert_eq!(
+            pacer.next_deadline(now, frame),
+            Some(now + frame + frame.div_f64(0.5))
+        );
+
+        // too far behind
+        let late = now + Duration::from_secs(1);
+        assert_eq!(pacer.next_deadline(late, frame), Some(late));
+    }
+
+    #[test]
+    fn test_pause() {
+        let mut pacer = Pacer::default();
+        assert!(pacer.should_run());
+
+        pacer.toggle_pause();
+        assert!(!pacer.should_run());
+        pacer.advance_frame();
+        assert!(pacer.should_run());
+        assert!(!pacer.should_run());
+
+        pacer.toggle_pause();
+        assert!(pacer.should_run());
+    }
+}
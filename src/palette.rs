@@ -0,0 +1,434 @@
+// Colors used to display monochrome (DMG) games.
+//
+// The CGB boot ROM colorizes monochrome games: it picks a palette from a table indexed by
+// the checksum of the title, or the player selects one by holding a button combination
+// while the logo is displayed.
+// ref. https://gbdev.io/pandocs/Power_Up_Sequence.html#compatibility-palettes
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::ppu::{DARKEST_GREEN, DARK_GREEN, LIGHTEST_GREEN, LIGHT_GREEN};
+
+/// RGB colors of the 4 shades (lightest first) for the background and both object palettes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Palette {
+    pub bg: [u32; 4],
+    pub obj0: [u32; 4],
+    pub obj1: [u32; 4],
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        DMG_GREEN
+    }
+}
+
+const DMG_GREEN: Palette =
+    Palette::uniform([LIGHTEST_GREEN, LIGHT_GREEN, DARK_GREEN, DARKEST_GREEN]);
+// Game Boy Pocket
+const POCKET_GREY: Palette = Palette::uniform(rgb([0xE0DBCD, 0xA89F94, 0x706B66, 0x2B2B26]));
+// pure white and black with the middle shades pushed apart
+const HIGH_CONTRAST: Palette = Palette::uniform(rgb([0xFFFFFF, 0xBFBFBF, 0x404040, 0x000000]));
+
+/// Built-in palettes by name.
+pub const PRESETS: [(&str, Palette); 3] = [
+    ("green", DMG_GREEN),
+    ("grey", POCKET_GREY),
+    ("high-contrast", HIGH_CONTRAST),
+];
+
+const fn rgb(colors: [u32; 4]) -> [u32; 4] {
+    [
+        0xFF000000 | colors[0],
+        0xFF000000 | colors[1],
+        0xFF000000 | colors[2],
+        0xFF000000 | colors[3],
+    ]
+}
+
+impl Palette {
+    pub const fn uniform(colors: [u32; 4]) -> Self {
+        Palette {
+            bg: colors,
+            obj0: colors,
+            obj1: colors,
+        }
+    }
+
+    /// Built-in palette by name, see `PRESETS`.
+    pub fn preset(name: &str) -> Option<Self> {
+        PRESETS
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, palette)| *palette)
+    }
+
+    /// Palette of a button combination held during the CGB boot logo, e.g. "left+b".
+    pub fn manual(combination: &str) -> Option<Self> {
+        MANUAL_COMBINATIONS
+            .iter()
+            .find(|(keys, _)| keys.eq_ignore_ascii_case(combination))
+            .map(|(_, index)| Palette::combination(*index))
+    }
+
+    /// Palette the CGB boot ROM selects for the game.
+    /// `header` is the cartridge header from $0134 to $014B.
+    pub fn compatibility(header: &[u8]) -> Self {
+        // 0134-0143 - Title
+        let title = &header[0x00..=0x0f];
+        // 0144-0145 - New Licensee Code
+        let new_licensee = &header[0x10..=0x11];
+        // 014B - Old Licensee Code, 33h means the new licensee code is used
+        let old_licensee = header[0x17];
+
+        // only games published by Nintendo are colorized
+        let nintendo = old_licensee == 0x01 || (old_licensee == 0x33 && new_licensee == b"01");
+        if !nintendo {
+            return Palette::combination(DEFAULT_COMBINATION);
+        }
+
+        let checksum = title.iter().fold(0u8, |sum, v| sum.wrapping_add(*v));
+        let index = match TITLE_CHECKSUMS.iter().position(|c| *c == checksum) {
+            Some(index) => index,
+            None => return Palette::combination(DEFAULT_COMBINATION),
+        };
+        if index < UNIQUE_CHECKSUMS {
+            return Palette::combination(PALETTE_PER_CHECKSUM[index]);
+        }
+
+        // some titles share a checksum and are told apart by the 4th letter
+        let column = index - UNIQUE_CHECKSUMS;
+        let ambiguous = TITLE_CHECKSUMS.len() - UNIQUE_CHECKSUMS;
+        FOURTH_LETTERS
+            .iter()
+            .skip(column)
+            .step_by(ambiguous)
+            .position(|letter| *letter == title[3])
+            .map(|row| Palette::combination(PALETTE_PER_CHECKSUM[index + row * ambiguous]))
+            .unwrap_or_else(|| Palette::combination(DEFAULT_COMBINATION))
+    }
+
+    // Palette combination of the boot ROM, see `COMBINATIONS`.
+    fn combination(index: u8) -> Self {
+        let [obj0, obj1, bg] = COMBINATIONS[index as usize];
+        let colors = |offset: u8| {
+            let mut colors = [0; 4];
+            for (i, color) in colors.iter_mut().enumerate() {
+                // RGB555 to RGB888
+                let c = BOOT_COLORS[offset as usize + i] as u32;
+                let (r, g, b) = (c & 0x1f, (c >> 5) & 0x1f, (c >> 10) & 0x1f);
+                let [r, g, b] = [r, g, b].map(|v| (v * 255 + 15) / 31);
+                *color = 0xFF000000 | r << 16 | g << 8 | b;
+            }
+            colors
+        };
+
+        Palette {
+            bg: colors(bg),
+            obj0: colors(obj0),
+            obj1: colors(obj1),
+        }
+    }
+
+    /// Reads a palette file.
+    ///
+    /// ```text
+    /// # shades from lightest to darkest as RRGGBB
+    /// bg   = FFFFFF A5A5A5 525252 000000
+    /// obj0 = FFFFFF FF8484 943A3A 000000
+    /// obj1 = FFFFFF 63A5FF 0000FF 000000
+    /// ```
+    ///
+    /// `obj0` and `obj1` default to the `bg` colors when omitted. Colors may be prefixed by `#`,
+    /// comments start with a `#` at the start of the line or after a space.
+    /// Colors may also be written as a TOML array, e.g. `bg = ["FFFFFF", "A5A5A5", "525252", "000000"]`.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        Palette::parse(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut bg = None;
+        let mut obj0 = None;
+        let mut obj1 = None;
+
+        for (i, line) in text.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let mut parts = line.splitn(2, '=');
+            let name = parts.next().unwrap_or("").trim();
+            let value = parts
+                .next()
+                .ok_or(format!("line {}: expected `name = colors`", i + 1))?;
+
+            let colors = parse_colors(value).map_err(|e| format!("line {}: {}", i + 1, e))?;
+            match name {
+                "bg" => bg = Some(colors),
+                "obj0" => obj0 = Some(colors),
+                "obj1" => obj1 = Some(colors),
+                _ => return Err(format!("line {}: unknown palette `{}`", i + 1, name)),
+            }
+        }
+
+        let bg = bg.ok_or("missing `bg` colors")?;
+        Ok(Palette {
+            bg,
+            obj0: obj0.unwrap_or(bg),
+            obj1: obj1.unwrap_or(bg),
+        })
+    }
+}
+
+// `#` starts a comment at the start of the line or after whitespace, unless it is the prefix
+// of a `#RRGGBB` color.
+fn strip_comment(line: &str) -> &str {
+    let bytes = line.as_bytes();
+    let comment = line.match_indices('#').find(|(i, _)| {
+        let after_space = *i == 0 || bytes[i - 1].is_ascii_whitespace();
+        let mut digits = bytes[i + 1..]
+            .iter()
+            .take_while(|b| b.is_ascii_alphanumeric());
+        let color = digits.clone().count() == 6 && digits.all(u8::is_ascii_hexdigit);
+        after_space && !color
+    });
+
+    match comment {
+        Some((i, _)) => &line[..i],
+        None => line,
+    }
+}
+
+fn parse_colors(value: &str) -> Result<[u32; 4], String> {
+    let colors = value
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .map(|c| c.trim_matches(|c| c == '[' || c == ']' || c == '"'))
+        .filter(|c| !c.is_empty())
+        .map(|c| {
+            let c = c.trim_start_matches('#');
+            match c.len() {
+                6 => u32::from_str_radix(c, 16).map_err(|e| format!("{}: {}", c, e)),
+                _ => Err(format!("{}: expected RRGGBB", c)),
+            }
+        })
+        .collect::<Result<Vec<u32>, String>>()?;
+
+    match colors.as_slice() {
+        [c0, c1, c2, c3] => Ok(rgb([*c0, *c1, *c2, *c3])),
+        _ => Err(format!("expected 4 colors, got {}", colors.len())),
+    }
+}
+
+// Used for games which aren't in the table (same as Right+A)
+const DEFAULT_COMBINATION: u8 = 0;
+
+// Index in COMBINATIONS of the button combinations
+const MANUAL_COMBINATIONS: [(&str, u8); 12] = [
+    ("up", 5),
+    ("up+a", 43),
+    ("up+b", 28),
+    ("left", 48),
+    ("left+a", 40),
+    ("left+b", 7),
+    ("down", 8),
+    ("down+a", 3),
+    ("down+b", 49),
+    ("right", 1),
+    ("right+a", 0),
+    ("right+b", 6),
+];
+
+// Palettes of the CGB boot ROM, found by the checksum of the title.
+// ref. https://github.com/LIJI32/SameBoy/blob/master/BootROMs/cgb_boot.asm
+const TITLE_CHECKSUMS: [u8; 79] = [
+    0x00, 0x88, 0x16, 0x36, 0xD1, 0xDB, 0xF2, 0x3C, 0x8C, 0x92, 0x3D, 0x5C, 0x58, 0xC9, 0x3E, 0x70,
+    0x1D, 0x59, 0x69, 0x19, 0x35, 0xA8, 0x14, 0xAA, 0x75, 0x95, 0x99, 0x34, 0x6F, 0x15, 0xFF, 0x97,
+    0x4B, 0x90, 0x17, 0x10, 0x39, 0xF7, 0xF6, 0xA2, 0x49, 0x4E, 0x43, 0x68, 0xE0, 0x8B, 0xF0, 0xCE,
+    0x0C, 0x29, 0xE8, 0xB7, 0x86, 0x9A, 0x52, 0x01, 0x9D, 0x71, 0x9C, 0xBD, 0x5D, 0x6D, 0x67, 0x3F,
+    0x6B, 0xB3, 0x46, 0x28, 0xA5, 0xC6, 0xD3, 0x27, 0x61, 0x18, 0x66, 0x6A, 0xBF, 0x0D, 0xF4,
+];
+
+// The checksums after these are ambiguous: the 4th letter of the title is looked up in
+// FOURTH_LETTERS, in rows as long as the ambiguous checksums.
+const UNIQUE_CHECKSUMS: usize = 65;
+const FOURTH_LETTERS: &[u8; 29] = b"BEFAARBEKEK R-URAR INAILICE R";
+
+// Index in COMBINATIONS for each title checksum, then for each of FOURTH_LETTERS
+const PALETTE_PER_CHECKSUM: [u8; 94] = [
+    0, 4, 5, 35, 34, 3, 31, 15, 10, 5, 19, 36, 7, 37, 30, 44, 21, 32, 31, 20, 5, 33, 13, 14, 5, 29,
+    5, 18, 9, 3, 2, 26, 25, 25, 41, 42, 26, 45, 42, 45, 36, 38, 26, 42, 30, 41, 34, 34, 5, 42, 6,
+    5, 33, 25, 42, 42, 40, 2, 16, 25, 42, 42, 5, 0, 39, 36, 22, 25, 6, 32, 12, 36, 11, 39, 18, 39,
+    24, 31, 50, 17, 46, 6, 27, 0, 47, 41, 41, 0, 0, 19, 34, 23, 18, 29,
+];
+
+// Offsets in BOOT_COLORS of the OBJ0, OBJ1 and BG palettes
+const COMBINATIONS: [[u8; 3]; 51] = [
+    palettes(4, 4, 29),             // 0, right+a
+    palettes(18, 18, 18),           // 1, right
+    palettes(20, 20, 20),           // 2
+    palettes(24, 24, 24),           // 3, down+a
+    palettes(9, 9, 9),              // 4
+    palettes(0, 0, 0),              // 5, up
+    palettes(27, 27, 27),           // 6, right+b
+    palettes(5, 5, 5),              // 7, left+b
+    palettes(12, 12, 12),           // 8, down
+    palettes(26, 26, 26),           // 9
+    palettes(16, 8, 8),             // 10
+    palettes(4, 28, 28),            // 11
+    palettes(4, 2, 2),              // 12
+    palettes(3, 4, 4),              // 13
+    palettes(4, 29, 29),            // 14
+    palettes(28, 4, 28),            // 15
+    palettes(2, 17, 2),             // 16
+    palettes(16, 16, 8),            // 17
+    palettes(4, 4, 7),              // 18
+    palettes(4, 4, 18),             // 19
+    palettes(4, 4, 20),             // 20
+    palettes(19, 19, 9),            // 21
+    [4 * 4 - 1, 4 * 4 - 1, 11 * 4], // 22
+    palettes(17, 17, 2),            // 23
+    palettes(4, 4, 2),              // 24
+    palettes(4, 4, 3),              // 25
+    palettes(28, 28, 0),            // 26
+    palettes(3, 3, 0),              // 27
+    palettes(0, 0, 1),              // 28, up+b
+    palettes(18, 22, 18),           // 29
+    palettes(20, 22, 20),           // 30
+    palettes(24, 22, 24),           // 31
+    palettes(16, 22, 8),            // 32
+    palettes(17, 4, 13),            // 33
+    [28 * 4 - 1, 0, 14 * 4],        // 34
+    [28 * 4 - 1, 4 * 4, 15 * 4],    // 35
+    palettes(19, 22, 9),            // 36
+    palettes(16, 28, 10),           // 37
+    palettes(4, 23, 28),            // 38
+    palettes(17, 22, 2),            // 39
+    palettes(4, 0, 2),              // 40, left+a
+    palettes(4, 28, 3),             // 41
+    palettes(28, 3, 0),             // 42
+    palettes(3, 28, 4),             // 43, up+a
+    palettes(21, 28, 4),            // 44
+    palettes(3, 28, 0),             // 45
+    palettes(25, 3, 28),            // 46
+    palettes(0, 28, 8),             // 47
+    palettes(4, 3, 28),             // 48, left
+    palettes(28, 3, 6),             // 49, down+b
+    palettes(4, 28, 29),            // 50
+];
+
+const fn palettes(obj0: u8, obj1: u8, bg: u8) -> [u8; 3] {
+    [obj0 * 4, obj1 * 4, bg * 4]
+}
+
+// 4 colors per palette, as RGB555
+const BOOT_COLORS: [u16; 120] = [
+    0x7FFF, 0x32BF, 0x00D0, 0x0000, 0x639F, 0x4279, 0x15B0, 0x04CB, 0x7FFF, 0x6E31, 0x454A, 0x0000,
+    0x7FFF, 0x1BEF, 0x0200, 0x0000, 0x7FFF, 0x421F, 0x1CF2, 0x0000, 0x7FFF, 0x5294, 0x294A, 0x0000,
+    0x7FFF, 0x03FF, 0x012F, 0x0000, 0x7FFF, 0x03EF, 0x01D6, 0x0000, 0x7FFF, 0x42B5, 0x3DC8, 0x0000,
+    0x7E74, 0x03FF, 0x0180, 0x0000, 0x67FF, 0x77AC, 0x1A13, 0x2D6B, 0x7ED6, 0x4BFF, 0x2175, 0x0000,
+    0x53FF, 0x4A5F, 0x7E52, 0x0000, 0x4FFF, 0x7ED2, 0x3A4C, 0x1CE0, 0x03ED, 0x7FFF, 0x255F, 0x0000,
+    0x036A, 0x021F, 0x03FF, 0x7FFF, 0x7FFF, 0x01DF, 0x0112, 0x0000, 0x231F, 0x035F, 0x00F2, 0x0009,
+    0x7FFF, 0x03EA, 0x011F, 0x0000, 0x299F, 0x001A, 0x000C, 0x0000, 0x7FFF, 0x027F, 0x001F, 0x0000,
+    0x7FFF, 0x03E0, 0x0206, 0x0120, 0x7FFF, 0x7EEB, 0x001F, 0x7C00, 0x7FFF, 0x3FFF, 0x7E00, 0x001F,
+    0x7FFF, 0x03FF, 0x001F, 0x0000, 0x03FF, 0x001F, 0x000C, 0x0000, 0x7FFF, 0x033F, 0x0193, 0x0000,
+    0x0000, 0x4200, 0x037F, 0x7FFF, 0x7FFF, 0x7E8C, 0x7C00, 0x0000, 0x7FFF, 0x1BEF, 0x6180, 0x0000,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::{rgb, Palette, DEFAULT_COMBINATION, HIGH_CONTRAST};
+
+    fn header(title: &[u8], old_licensee: u8) -> Vec<u8> {
+        let mut header = vec![0; 0x18];
+        header[..title.len()].copy_from_slice(title);
+        header[0x17] = old_licensee;
+        header
+    }
+
+    #[test]
+    fn test_compatibility() {
+        // POKEMON RED
+        assert_eq!(
+            Palette::compatibility(&header(b"POKEMON RED", 0x01)),
+            Palette::combination(13)
+        );
+        // not published by Nintendo
+        assert_eq!(
+            Palette::compatibility(&header(b"POKEMON RED", 0x33)),
+            Palette::combination(DEFAULT_COMBINATION)
+        );
+        // not in the table
+        assert_eq!(
+            Palette::compatibility(&header(b"GBRUST", 0x01)),
+            Palette::combination(DEFAULT_COMBINATION)
+        );
+    }
+
+    #[test]
+    fn test_compatibility_fourth_letter() {
+        // same checksum, told apart by the 4th letter, in the first and second rows
+        assert_eq!(
+            Palette::compatibility(&header(b"POKEMON BLUE", 0x01)),
+            Palette::combination(11)
+        );
+        assert_eq!(
+            Palette::compatibility(&header(b"VEGAS STAKES", 0x01)),
+            Palette::combination(41)
+        );
+        // neither
+        assert_eq!(
+            Palette::compatibility(&header(b"POKXMON BLUE", 0x01)),
+            Palette::combination(DEFAULT_COMBINATION)
+        );
+    }
+
+    #[test]
+    fn test_manual() {
+        assert_eq!(
+            Palette::manual("Left+B"),
+            Some(Palette::uniform(rgb([
+                0xFFFFFF, 0xA5A5A5, 0x525252, 0x000000
+            ])))
+        );
+        // BG blue, OBJ0 red, OBJ1 green
+        assert_eq!(
+            Palette::manual("left"),
+            Some(Palette {
+                bg: rgb([0xFFFFFF, 0x63A5FF, 0x0000FF, 0x000000]),
+                obj0: rgb([0xFFFFFF, 0xFF8484, 0x943A3A, 0x000000]),
+                obj1: rgb([0xFFFFFF, 0x7BFF31, 0x008400, 0x000000]),
+            })
+        );
+        assert_eq!(Palette::manual("right+a"), Some(Palette::combination(0)));
+        assert_eq!(Palette::manual("up+down"), None);
+    }
+
+    #[test]
+    fn test_parse() {
+        let palette = Palette::parse("# grayscale\nbg = FFFFFF A5A5A5 525252 000000\n").unwrap();
+        assert_eq!(palette, Palette::manual("left+b").unwrap());
+
+        // TOML
+        let palette = Palette::parse(
+            "bg = [\"FFFFFF\", \"BFBFBF\", \"404040\", \"000000\"] # high contrast\n",
+        )
+        .unwrap();
+        assert_eq!(palette, Palette::preset("high-contrast").unwrap());
+        assert_eq!(palette, HIGH_CONTRAST);
+
+        // #RRGGBB colors and comments after them
+        let palette = Palette::parse(
+            "# high contrast\nbg = #FFFFFF #BFBFBF #404040 #000000 # shades\n\
+             obj0 = [\"#FFFFFF\", \"#BFBFBF\", \"#404040\", \"#000000\"] # obj0\n",
+        )
+        .unwrap();
+        assert_eq!(palette, HIGH_CONTRAST);
+
+        assert!(Palette::parse("bg = FFFFFF").is_err());
+        assert!(Palette::parse("obj0 = FFFFFF A5A5A5 525252 000000").is_err());
+    }
+}
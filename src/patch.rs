@@ -0,0 +1,469 @@
+// ROM patches: IPS, UPS and BPS, recognized by their header.
+//
+// IPS: "PATCH", records of offset (3 bytes) + size (2) + data, or size 0 + count (2) + value
+//      (run-length encoded), "EOF" and an optional size (3) to truncate the ROM to.
+//      Numbers are big endian.
+// ref. https://zerosoft.zophar.net/ips.php
+//
+// UPS: "UPS1", source and target sizes, hunks of a relative offset + bytes XORed to the
+//      ROM ending with 0, CRC32 of the source, the target and the patch.
+// ref. http://individual.utoronto.ca/dmeunier/ups-spec.pdf
+//
+// BPS: "BPS1", source and target sizes, metadata, actions copying from the source, the patch
+//      or the target written so far, CRC32 of the source, the target and the patch.
+// ref. https://github.com/blakesmith/rombp/blob/master/docs/bps_spec.md
+
+use std::convert::TryFrom;
+use std::path::{Path, PathBuf};
+
+use flate2::Crc;
+
+// The largest cartridges are 8MB, larger target sizes are corrupted patches.
+pub(crate) const MAX_TARGET_SIZE: usize = 8 * 1024 * 1024;
+
+/// Extensions of the patches looked for next to a ROM.
+pub const EXTENSIONS: [&str; 3] = ["ips", "ups", "bps"];
+
+/// The patch with the same name as the ROM, e.g. game.ips for game.gb.
+pub fn find<P: AsRef<Path>>(rom_path: P) -> Option<PathBuf> {
+    EXTENSIONS
+        .iter()
+        .map(|extension| rom_path.as_ref().with_extension(extension))
+        .find(|path| path.is_file())
+}
+
+/// `rom` with `patch` applied.
+pub fn apply(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    if patch.starts_with(b"PATCH") {
+        apply_ips(rom, patch)
+    } else if patch.starts_with(b"UPS1") {
+        apply_ups(rom, patch)
+    } else if patch.starts_with(b"BPS1") {
+        apply_bps(rom, patch)
+    } else {
+        Err("unknown patch format".to_string())
+    }
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = Crc::new();
+    crc.update(data);
+    crc.sum()
+}
+
+// Reads the patch from the start.
+struct Reader<'a> {
+    patch: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .patch
+            .get(self.offset..self.offset.saturating_add(len))
+            .ok_or("unexpected end of the patch")?;
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    // big endian
+    fn number(&mut self, len: usize) -> Result<usize, String> {
+        Ok(self.bytes(len)?.iter().fold(0, |n, b| n << 8 | *b as usize))
+    }
+
+    // variable length number of UPS and BPS, 7 bits per byte, the last byte has bit 7 set
+    fn varint(&mut self) -> Result<usize, String> {
+        let invalid = || "invalid number in the patch".to_string();
+        let mut value = 0usize;
+        let mut shift = 1usize;
+        loop {
+            let byte = self.byte()?;
+            let bits = ((byte & 0x7f) as usize)
+                .checked_mul(shift)
+                .ok_or_else(invalid)?;
+            value = value.checked_add(bits).ok_or_else(invalid)?;
+            if byte & 0x80 > 0 {
+                return Ok(value);
+            }
+            // the next 7 bits would be past the highest bit
+            if shift.leading_zeros() < 7 {
+                return Err(invalid());
+            }
+            shift <<= 7;
+            value = value.checked_add(shift).ok_or_else(invalid)?;
+        }
+    }
+}
+
+fn apply_ips(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    let mut output = rom.to_vec();
+    let mut reader = Reader { patch, offset: 5 };
+
+    loop {
+        let record = reader.bytes(3)?;
+        if record == b"EOF" {
+            break;
+        }
+        let offset = record.iter().fold(0, |n, b| n << 8 | *b as usize);
+
+        let size = reader.number(2)?;
+        let data = match size {
+            0 => {
+                let count = reader.number(2)?;
+                vec![reader.byte()?; count]
+            }
+            _ => reader.bytes(size)?.to_vec(),
+        };
+
+        // records may write past the end of the ROM
+        check_target_size(offset + data.len())?;
+        if output.len() < offset + data.len() {
+            output.resize(offset + data.len(), 0);
+        }
+        output[offset..offset + data.len()].copy_from_slice(&data);
+    }
+
+    // truncation extension
+    if let Ok(size) = reader.number(3) {
+        output.truncate(size);
+    }
+
+    Ok(output)
+}
+
+fn check_target_size(target_size: usize) -> Result<(), String> {
+    match target_size {
+        0..=MAX_TARGET_SIZE => Ok(()),
+        _ => Err(format!(
+            "the patched ROM would be {} bytes, more than {}",
+            target_size, MAX_TARGET_SIZE
+        )),
+    }
+}
+
+// Checks the CRC32 of the source, the target and the patch at the end of UPS and BPS patches.
+fn check_footer(source: &[u8], target: &[u8], patch: &[u8]) -> Result<(), String> {
+    let footer = &patch[patch.len() - 12..];
+    let crc =
+        |i: usize| u32::from_le_bytes([footer[i], footer[i + 1], footer[i + 2], footer[i + 3]]);
+
+    if crc(8) != crc32(&patch[..patch.len() - 4]) {
+        return Err("the patch is corrupted".to_string());
+    }
+    if crc(0) != crc32(source) {
+        return Err("the patch is for another ROM".to_string());
+    }
+    if crc(4) != crc32(target) {
+        return Err("the patched ROM is wrong".to_string());
+    }
+
+    Ok(())
+}
+
+fn apply_ups(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    if patch.len() < 4 + 12 {
+        return Err("unexpected end of the patch".to_string());
+    }
+    let end = patch.len() - 12;
+    let mut reader = Reader { patch, offset: 4 };
+
+    let source_size = reader.varint()?;
+    let target_size = reader.varint()?;
+    check_target_size(target_size)?;
+    if source_size != rom.len() {
+        return Err(format!(
+            "the patch is for a ROM of {} bytes, not {}",
+            source_size,
+            rom.len()
+        ));
+    }
+
+    let mut output = rom.to_vec();
+    output.resize(target_size, 0);
+
+    let out_of_range = || "the patch writes out of the ROM".to_string();
+    let mut pointer: usize = 0;
+    while reader.offset < end {
+        pointer = pointer
+            .checked_add(reader.varint()?)
+            .ok_or_else(out_of_range)?;
+        loop {
+            let byte = reader.byte()?;
+            if byte != 0 && pointer < output.len() {
+                output[pointer] ^= byte;
+            }
+            pointer = pointer.checked_add(1).ok_or_else(out_of_range)?;
+            if byte == 0 {
+                break;
+            }
+        }
+    }
+
+    check_footer(rom, &output, patch)?;
+    Ok(output)
+}
+
+fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    if patch.len() < 4 + 12 {
+        return Err("unexpected end of the patch".to_string());
+    }
+    let end = patch.len() - 12;
+    let mut reader = Reader { patch, offset: 4 };
+
+    let source_size = reader.varint()?;
+    let target_size = reader.varint()?;
+    check_target_size(target_size)?;
+    let metadata_size = reader.varint()?;
+    reader.bytes(metadata_size)?;
+    if source_size != rom.len() {
+        return Err(format!(
+            "the patch is for a ROM of {} bytes, not {}",
+            source_size,
+            rom.len()
+        ));
+    }
+
+    let out_of_range = || "the patch reads out of the ROM".to_string();
+    let mut output = Vec::with_capacity(target_size);
+    let mut source_offset: isize = 0;
+    let mut target_offset: isize = 0;
+
+    // offsets of the copy actions are relative to the previous ones
+    let relative = |reader: &mut Reader, offset: &mut isize| -> Result<usize, String> {
+        let data = reader.varint()?;
+        let delta = (data >> 1) as isize;
+        *offset = match data & 1 {
+            1 => offset.checked_sub(delta),
+            _ => offset.checked_add(delta),
+        }
+        .ok_or_else(out_of_range)?;
+        usize::try_from(*offset).map_err(|_| out_of_range())
+    };
+
+    while reader.offset < end {
+        let data = reader.varint()?;
+        let length = (data >> 2) + 1;
+        if output.len() + length > target_size {
+            return Err(format!(
+                "the patched ROM is more than the {} bytes expected",
+                target_size
+            ));
+        }
+
+        match data & 3 {
+            // SourceRead: the source at the same offset as the output
+            0 => {
+                let start = output.len();
+                let bytes = rom.get(start..start + length).ok_or_else(out_of_range)?;
+                output.extend_from_slice(bytes);
+            }
+            // TargetRead: bytes of the patch
+            1 => output.extend_from_slice(reader.bytes(length)?),
+            // SourceCopy
+            2 => {
+                let start = relative(&mut reader, &mut source_offset)?;
+                let bytes = rom.get(start..start + length).ok_or_else(out_of_range)?;
+                output.extend_from_slice(bytes);
+                source_offset += length as isize;
+            }
+            // TargetCopy: byte by byte, the copy can overlap the bytes it writes
+            _ => {
+                let start = relative(&mut reader, &mut target_offset)?;
+                for i in start..start + length {
+                    let byte = *output.get(i).ok_or_else(out_of_range)?;
+                    output.push(byte);
+                }
+                target_offset += length as isize;
+            }
+        }
+    }
+
+    if output.len() != target_size {
+        return Err(format!(
+            "the patched ROM is {} bytes, expected {}",
+            output.len(),
+            target_size
+        ));
+    }
+
+    check_footer(rom, &output, patch)?;
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply, crc32};
+
+    fn varint(mut value: usize) -> Vec<u8> {
+        let mut bytes = vec![];
+        loop {
+            let x = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                bytes.push(0x80 | x);
+                return bytes;
+            }
+            bytes.push(x);
+            value -= 1;
+        }
+    }
+
+    fn footer(source: &[u8], target: &[u8], mut patch: Vec<u8>) -> Vec<u8> {
+        patch.extend_from_slice(&crc32(source).to_le_bytes());
+        patch.extend_from_slice(&crc32(target).to_le_bytes());
+        let crc = crc32(&patch);
+        patch.extend_from_slice(&crc.to_le_bytes());
+        patch
+    }
+
+    #[test]
+    fn test_ips() {
+        let rom = [0u8; 8];
+        let mut patch = b"PATCH".to_vec();
+        // 2 bytes at 1
+        patch.extend_from_slice(&[0, 0, 1, 0, 2, 0xaa, 0xbb]);
+        // 3 times 0xcc at 6, past the end
+        patch.extend_from_slice(&[0, 0, 6, 0, 0, 0, 3, 0xcc]);
+        patch.extend_from_slice(b"EOF");
+
+        assert_eq!(
+            apply(&rom, &patch),
+            Ok(vec![0, 0xaa, 0xbb, 0, 0, 0, 0xcc, 0xcc, 0xcc])
+        );
+
+        // truncated to 4 bytes
+        patch.extend_from_slice(&[0, 0, 4]);
+        assert_eq!(apply(&rom, &patch), Ok(vec![0, 0xaa, 0xbb, 0]));
+
+        assert!(apply(&rom, b"PATCH\0\0\x01\0\x02\xaa").is_err());
+        assert!(apply(&rom, b"NOT A PATCH").is_err());
+    }
+
+    #[test]
+    fn test_ups() {
+        let source = [1u8, 2, 3, 4, 5, 6];
+        let target = [1u8, 2, 0x33, 4, 5, 6, 7];
+
+        let mut patch = b"UPS1".to_vec();
+        patch.extend(varint(source.len()));
+        patch.extend(varint(target.len()));
+        patch.extend(varint(2));
+        patch.extend_from_slice(&[3 ^ 0x33, 0]);
+        // after the 0 ending the hunk, 4
+        patch.extend(varint(2));
+        patch.extend_from_slice(&[7, 0]);
+        let patch = footer(&source, &target, patch);
+
+        assert_eq!(apply(&source, &patch), Ok(target.to_vec()));
+        assert!(apply(&[1, 2, 3, 4, 5, 7], &patch).is_err());
+
+        let mut corrupted = patch.clone();
+        corrupted[8] ^= 1;
+        assert!(apply(&source, &corrupted).is_err());
+    }
+
+    #[test]
+    fn test_bps() {
+        let source = b"ABCDEFGH";
+        let target = b"ABCDxyxyxyGH";
+
+        let mut patch = b"BPS1".to_vec();
+        patch.extend(varint(source.len()));
+        patch.extend(varint(target.len()));
+        patch.extend(varint(0));
+        // SourceRead 4
+        patch.extend(varint((4 - 1) << 2));
+        // TargetRead "xy"
+        patch.extend(varint(((2 - 1) << 2) | 1));
+        patch.extend_from_slice(b"xy");
+        // TargetCopy 4 from 4, overlapping
+        patch.extend(varint(((4 - 1) << 2) | 3));
+        patch.extend(varint(4 << 1));
+        // SourceCopy 2 from 6
+        patch.extend(varint(((2 - 1) << 2) | 2));
+        patch.extend(varint(6 << 1));
+        let patch = footer(source, target, patch);
+
+        assert_eq!(apply(source, &patch), Ok(target.to_vec()));
+        assert!(apply(b"ABCDEFGX", &patch).is_err());
+    }
+
+    #[test]
+    fn test_target_size() {
+        let source = [0u8; 4];
+
+        // UPS and BPS to 1GB
+        for format in [b"UPS1", b"BPS1"] {
+            let mut patch = format.to_vec();
+            patch.extend(varint(source.len()));
+            patch.extend(varint(1 << 30));
+            patch.extend(varint(0));
+            let patch = footer(&source, &[], patch);
+            assert!(apply(&source, &patch).is_err());
+        }
+
+        // a TargetCopy repeating a byte past the target size
+        let mut patch = b"BPS1".to_vec();
+        patch.extend(varint(source.len()));
+        patch.extend(varint(2));
+        patch.extend(varint(0));
+        patch.extend(varint(1 << 2));
+        patch.extend(varint(((1 << 40) << 2) | 3));
+        patch.extend(varint(0));
+        let patch = footer(&source, &[0, 0], patch);
+        assert!(apply(&source, &patch).is_err());
+
+        // IPS records past 8MB
+        let mut patch = b"PATCH".to_vec();
+        patch.extend_from_slice(&[0xff, 0xff, 0xff, 0, 1, 0xaa]);
+        patch.extend_from_slice(b"EOF");
+        assert!(apply(&source, &patch).is_err());
+    }
+
+    #[test]
+    fn test_overflow() {
+        let source = [0u8; 4];
+
+        // a number of more than 64 bits
+        let mut patch = b"UPS1".to_vec();
+        patch.extend_from_slice(&[0; 9]);
+        patch.push(0x82);
+        let patch = footer(&source, &source, patch);
+        assert_eq!(
+            apply(&source, &patch),
+            Err("invalid number in the patch".to_string())
+        );
+
+        // a UPS hunk at the highest offset
+        let mut patch = b"UPS1".to_vec();
+        patch.extend(varint(source.len()));
+        patch.extend(varint(source.len()));
+        patch.extend(varint(usize::MAX));
+        patch.push(0);
+        let patch = footer(&source, &source, patch);
+        assert_eq!(
+            apply(&source, &patch),
+            Err("the patch writes out of the ROM".to_string())
+        );
+
+        // a BPS SourceCopy past the highest offset
+        let mut patch = b"BPS1".to_vec();
+        patch.extend(varint(source.len()));
+        patch.extend(varint(2));
+        patch.extend(varint(0));
+        patch.extend(varint(2));
+        patch.extend(varint(3 << 1));
+        patch.extend(varint(2));
+        patch.extend(varint((isize::MAX as usize) << 1));
+        let patch = footer(&source, &[0, 0], patch);
+        assert_eq!(
+            apply(&source, &patch),
+            Err("the patch reads out of the ROM".to_string())
+        );
+    }
+}
@@ -1,13 +1,21 @@
 use std::fmt;
 
+use crate::palette::Palette;
+
 pub struct Ppu {
     mode: u8,
-    // 8KB Video RAM(VRAM)
+    // Game Boy Color mode
+    cgb: bool,
+    // 8KB Video RAM(VRAM), 2 banks on CGB
     vram: Vec<u8>,
+    // VBK - CGB Mode Only - VRAM Bank
+    vbk: u8,
     // OAM
     // from $FE00-$FE9F
     oam: Vec<u8>,
     pub buffer: Vec<u32>,
+    // DMG: shade (0-3) of each pixel before the palette is applied, colored by the SGB
+    pub shades: Vec<u8>,
     clocks: usize,
     // I/O Registers
     // LCD Control Register
@@ -18,15 +26,41 @@ pub struct Ppu {
     scy: u8,
     // Y-Coordinate (R)
     ly: u8,
+    // STAT - LCDC Status, only the interrupt selection bits (3-6) are writable
+    stat: u8,
+    // LYC - LY Compare
+    lyc: u8,
     // Window X Position minus 7
     wx: u8,
     // Window Y Position minus 7
     wy: u8,
     // BGP - BG Palette Data
     bgp: u8,
+    // OBP0, OBP1 - Object Palette Data
+    obp0: u8,
+    obp1: u8,
+    // BCPS/BGPI, OCPS/OBPI - CGB Mode Only - Palette Index
+    // Bit 7     Auto Increment  (0=Disabled, 1=Increment after Writing)
+    // Bit 5-0   Index (00-3F)
+    bcps: u8,
+    ocps: u8,
+    // BCPD/BGPD, OCPD/OBPD - CGB Mode Only - Palette Data
+    // 8 palettes * 4 colors * 2 bytes (RGB555, little endian)
+    bg_palette: [u8; 0x40],
+    obj_palette: [u8; 0x40],
+    // DMG: colors of BG, OBJ0 and OBJ1
+    palette: Palette,
     scanline: [u8; WIDTH],
+    // palette of each pixel in scanline
+    // DMG: 0: BG, 1: OBJ0, 2: OBJ1
+    // CGB: 0-7: BG, 8-15: OBJ
+    scanline_palette: [u8; WIDTH],
+    // CGB: BG-to-OAM Priority of each pixel in scanline
+    scanline_priority: [bool; WIDTH],
     // VBlank
     pub vblank: bool,
+    // entered HBlank (mode 0)
+    pub hblank: bool,
     pub debug: bool,
 }
 
@@ -34,6 +68,7 @@ pub const WIDTH: usize = 160;
 pub const HEIGHT: usize = 144;
 
 pub const VRAM_ADDRESS_BASE: u16 = 0x8000;
+pub const VRAM_BANK_SIZE: usize = 0x2000;
 
 // PG Palette Data
 pub const DARKEST_GREEN: u32 = 0xFF0F380F;
@@ -59,25 +94,57 @@ impl Default for Ppu {
     fn default() -> Self {
         Ppu {
             mode: 2,
-            vram: vec![0; 0x2000],
+            cgb: false,
+            vram: vec![0; VRAM_BANK_SIZE * 2],
+            vbk: 0,
             oam: vec![0; 0xa0],
             buffer: vec![DARKEST_GREEN; WIDTH * HEIGHT],
+            shades: vec![0; WIDTH * HEIGHT],
             clocks: 0,
             lcdc: 0b1000_0000,
             scx: 0,
             scy: 0,
             ly: 0,
+            stat: 0,
+            lyc: 0,
             wx: 0,
             wy: 0,
             bgp: 0,
+            obp0: 0,
+            obp1: 0,
+            bcps: 0,
+            ocps: 0,
+            // all colors are white after boot
+            bg_palette: [0xff; 0x40],
+            obj_palette: [0xff; 0x40],
+            palette: Palette::default(),
             scanline: [0; WIDTH],
+            scanline_palette: [0; WIDTH],
+            scanline_priority: [false; WIDTH],
             vblank: false,
+            hblank: false,
             debug: false,
         }
     }
 }
 
 impl Ppu {
+    pub fn new(cgb: bool) -> Self {
+        Ppu {
+            cgb,
+            ..Ppu::default()
+        }
+    }
+
+    pub fn is_cgb(&self) -> bool {
+        self.cgb
+    }
+
+    /// Colors used for monochrome games.
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+    }
+
     pub fn step(&mut self, clocks: usize) {
         if self.lcdc & 0b1000_0000 == 0 {
             return;
@@ -100,6 +167,7 @@ impl Ppu {
                 if self.clocks >= 172 {
                     self.mode = 0;
                     self.clocks = 0;
+                    self.hblank = true;
 
                     self.redner_scanline();
                 }
@@ -110,7 +178,7 @@ impl Ppu {
                     self.clocks = 0;
                     self.ly += 1;
 
-                    if self.ly == 143 {
+                    if self.ly == 144 {
                         // Enter vblank
                         self.mode = 1;
                         self.vblank = true;
@@ -141,6 +209,20 @@ impl Ppu {
         }
     }
 
+    fn is_lcd_enabled(&self) -> bool {
+        self.lcdc & 0b1000_0000 > 0
+    }
+
+    /// VRAM can't be accessed by the CPU while the PPU is drawing (mode 3).
+    pub fn is_vram_accessible(&self) -> bool {
+        !self.is_lcd_enabled() || self.mode != 3
+    }
+
+    /// OAM can't be accessed by the CPU during OAM scan and drawing (mode 2 and 3).
+    pub fn is_oam_accessible(&self) -> bool {
+        !self.is_lcd_enabled() || self.mode == 0 || self.mode == 1
+    }
+
     fn render_background(&mut self) {
         // TODO: lcdc
         let tile_map_base: u16 = 0x9800 - VRAM_ADDRESS_BASE;
@@ -154,7 +236,7 @@ impl Ppu {
             let index_y = pixel_y / 8;
 
             // offsets within tile (0 ~ 7)
-            let offset_x = pixel_x % 8;
+            let mut offset_x = pixel_x % 8;
             let mut offset_y = pixel_y % 8;
 
             // LCDC: Bit 5 - Window Display Enable          (0=Off, 1=On)
@@ -171,6 +253,27 @@ impl Ppu {
             // get the tile No
             let tile_no = self.vram[tile_map_address as usize];
 
+            // CGB: BG Map Attributes, at the same address in VRAM bank 1
+            // Bit 0-2  Background Palette number  (BGP0-7)
+            // Bit 3    Tile VRAM Bank number      (0=Bank 0, 1=Bank 1)
+            // Bit 5    Horizontal Flip            (0=Normal, 1=Mirror horizontally)
+            // Bit 6    Vertical Flip              (0=Normal, 1=Mirror vertically)
+            // Bit 7    BG-to-OAM Priority         (0=Use OAM priority bit, 1=BG Priority)
+            let attributes = match self.cgb {
+                true => self.vram[VRAM_BANK_SIZE + tile_map_address as usize],
+                false => 0,
+            };
+            if attributes & 0x20 > 0 {
+                offset_x = 7 - offset_x;
+            }
+            if attributes & 0x40 > 0 {
+                offset_y = 7 - offset_y;
+            }
+            let bank = match attributes & 0x08 > 0 {
+                true => VRAM_BANK_SIZE,
+                false => 0,
+            };
+
             // calculate tile set address
             let tile_set_address = 0x8000 + (tile_no as u16) * 16 - VRAM_ADDRESS_BASE;
 
@@ -178,8 +281,8 @@ impl Ppu {
             let tile_data_address = tile_set_address + (offset_y * 2) as u16;
 
             // get tile data
-            let tile_data_low = self.vram[tile_data_address as usize];
-            let tile_data_high = self.vram[(tile_data_address + 1) as usize];
+            let tile_data_low = self.vram[bank + tile_data_address as usize];
+            let tile_data_high = self.vram[bank + (tile_data_address + 1) as usize];
 
             let mask = 1 << (7 - offset_x);
             let lsb = tile_data_low & mask;
@@ -192,6 +295,14 @@ impl Ppu {
                 (false, false) => 0,
             };
 
+            if self.cgb {
+                // the color comes from the palette RAM
+                self.scanline[x] = palette_no;
+                self.scanline_palette[x] = attributes & 0x07;
+                self.scanline_priority[x] = attributes & 0x80 > 0;
+                continue;
+            }
+
             // Bit 7-6 - Shade for Color Number 3
             // Bit 5-4 - Shade for Color Number 2
             // Bit 3-2 - Shade for Color Number 1
@@ -199,6 +310,7 @@ impl Ppu {
             let color_no = (self.bgp >> (palette_no * 2)) & 0x03;
 
             self.scanline[x] = color_no;
+            self.scanline_palette[x] = 0;
         }
     }
 
@@ -221,6 +333,11 @@ impl Ppu {
             let x = self.oam[address] - 8;
             let y = self.oam[address + 1] - 16;
             let tile_number = self.oam[address + 2];
+            // Bit 7   OBJ-to-BG Priority (0=OBJ Above BG, 1=OBJ Behind BG color 1-3)
+            // Bit 4   Palette number  **Non CGB Mode Only** (0=OBP0, 1=OBP1)
+            // Bit 3   Tile VRAM-Bank  (0=Bank 0, 1=Bank 1)
+            // Bit 2-0 Palette number  (OBP0-7)
+            let flags = self.oam[address + 3];
 
             if self.ly < y || y + 8 <= self.ly {
                 continue;
@@ -235,8 +352,13 @@ impl Ppu {
             // 1 tile 2 bytes
             let row_address = tile_set_address + (offset_y << 1) as u16;
 
-            let tile0 = self.vram[row_address as usize];
-            let tile1 = self.vram[(row_address + 1) as usize];
+            let bank = match self.cgb && flags & 0x08 > 0 {
+                true => VRAM_BANK_SIZE,
+                false => 0,
+            };
+
+            let tile0 = self.vram[bank + row_address as usize];
+            let tile1 = self.vram[bank + (row_address + 1) as usize];
 
             for offset_x in 0..8 {
                 // color number (0, 1, 2, 3)
@@ -251,7 +373,29 @@ impl Ppu {
                     (false, false) => 0,
                 };
 
-                self.scanline[(x + offset_x) as usize] = color;
+                let pixel = (x + offset_x) as usize;
+
+                // color 0 is transparent
+                if color == 0 {
+                    continue;
+                }
+
+                if self.cgb {
+                    let behind_bg = self.scanline_priority[pixel] || flags & 0x80 > 0;
+                    if behind_bg && self.scanline[pixel] != 0 {
+                        continue;
+                    }
+                    self.scanline_palette[pixel] = 8 + (flags & 0x07);
+                    self.scanline[pixel] = color;
+                } else {
+                    // shaded by OBP0 or OBP1, like the BG by BGP
+                    let obp = match flags & 0x10 > 0 {
+                        true => self.obp1,
+                        false => self.obp0,
+                    };
+                    self.scanline_palette[pixel] = 1 + ((flags >> 4) & 0x01);
+                    self.scanline[pixel] = (obp >> (color * 2)) & 0x03;
+                }
             }
         }
     }
@@ -272,48 +416,102 @@ impl Ppu {
         for x in 0..WIDTH {
             let index = (x as usize) + (self.ly as usize) * WIDTH;
             let color_no = self.scanline[x];
-            self.buffer[index] = self.color_no_to_rgb(color_no);
+            self.shades[index] = color_no;
+            self.buffer[index] = match self.cgb {
+                true => self.cgb_color_to_rgb(self.scanline_palette[x], color_no),
+                false => self.color_no_to_rgb(self.scanline_palette[x], color_no),
+            };
         }
     }
 
-    fn color_no_to_rgb(&self, no: u8) -> u32 {
+    // palette: 0 BG, 1 OBJ0, 2 OBJ1
+    fn color_no_to_rgb(&self, palette: u8, no: u8) -> u32 {
+        let colors = match palette {
+            0 => &self.palette.bg,
+            1 => &self.palette.obj0,
+            2 => &self.palette.obj1,
+            _ => panic!("unrecognized palette #{:?}", palette),
+        };
+
         match no {
-            0 => LIGHTEST_GREEN,
-            1 => LIGHT_GREEN,
-            2 => DARK_GREEN,
-            3 => DARKEST_GREEN,
+            0..=3 => colors[no as usize],
             _ => panic!("unrecognized color no #{:?}", no),
         }
     }
 
+    // palette: 0-7 BG, 8-15 OBJ
+    fn cgb_color_to_rgb(&self, palette: u8, color_no: u8) -> u32 {
+        let (palette_ram, palette) = match palette {
+            0..=7 => (&self.bg_palette, palette),
+            _ => (&self.obj_palette, palette - 8),
+        };
+
+        let index = (palette as usize) * 8 + (color_no as usize) * 2;
+        let color = (palette_ram[index] as u16) | ((palette_ram[index + 1] as u16) << 8);
+
+        rgb555_to_rgb888(color)
+    }
+
+    /// SGB VRAM transfer: the 4KB of tile data of the first 256 tiles on the screen,
+    /// taken row by row (20 tiles per row) from the BG map.
+    pub fn vram_transfer(&self) -> Vec<u8> {
+        // LCDC Bit 3 - BG Tile Map Display Select (0=9800-9BFF, 1=9C00-9FFF)
+        let tile_map_base = match self.lcdc & 0x08 {
+            0 => 0x1800,
+            _ => 0x1c00,
+        };
+
+        let mut data = Vec::with_capacity(0x1000);
+        for i in 0..256 {
+            let tile_no = self.vram[tile_map_base + (i / 20) * 32 + i % 20];
+            // LCDC Bit 4 - BG & Window Tile Data Select (0=8800-97FF, 1=8000-8FFF)
+            let tile_address = match self.lcdc & 0x10 {
+                0 => (0x1000 + (tile_no as i8 as isize) * 16) as usize,
+                _ => (tile_no as usize) * 16,
+            };
+            data.extend_from_slice(&self.vram[tile_address..tile_address + 16]);
+        }
+
+        data
+    }
+
+    fn vram_offset(&self, address: u16) -> usize {
+        (self.vbk as usize) * VRAM_BANK_SIZE + (address - VRAM_ADDRESS_BASE) as usize
+    }
+
     pub fn read(&self, address: u16) -> u8 {
         match address {
-            0x8000..=0x9fff => self.vram[(address - VRAM_ADDRESS_BASE) as usize],
+            0x8000..=0x9fff => self.vram[self.vram_offset(address)],
 
             // 0AM
             0xfe00..=0xfe9f => self.oam[(address & 0x00ff) as usize],
 
             0xff40 => self.lcdc,
+            0xff41 => {
+                // Bit 7 - always 1
+                // Bit 2 - Coincidence Flag  (0:LYC<>LY, 1:LYC=LY)
+                // Bit 1-0 - Mode Flag, 0 while the LCD is off
+                let coincidence = if self.ly == self.lyc { 0x04 } else { 0x00 };
+                let mode = if self.is_lcd_enabled() { self.mode } else { 0 };
+                0x80 | self.stat | coincidence | mode
+            }
             0xff42 => self.scy,
             0xff43 => self.scx,
             0xff44 => self.ly,
+            0xff45 => self.lyc,
             0xff47 => self.bgp,
+            0xff48 => self.obp0,
+            0xff49 => self.obp1,
             0xff4a => self.wy,
             0xff4b => self.wx,
 
-            // TODO:
-            0xff41 => {
-                panic!("stat read");
-            }
-            0xff45 => {
-                panic!("lyc read");
-            }
-            0xff48 => {
-                panic!("obp0 read");
-            }
-            0xff49 => {
-                panic!("obp1 read");
-            }
+            // CGB registers
+            0xff4f | 0xff68..=0xff6b if !self.cgb => 0xff,
+            0xff4f => 0xfe | self.vbk,
+            0xff68 => 0x40 | self.bcps,
+            0xff69 => self.bg_palette[(self.bcps & 0x3f) as usize],
+            0xff6a => 0x40 | self.ocps,
+            0xff6b => self.obj_palette[(self.ocps & 0x3f) as usize],
 
             _ => panic!("unexpected address #{:x}", address),
         }
@@ -322,7 +520,8 @@ impl Ppu {
     pub fn write(&mut self, address: u16, value: u8) {
         match address {
             0x8000..=0x9fff => {
-                self.vram[(address - VRAM_ADDRESS_BASE) as usize] = value;
+                let offset = self.vram_offset(address);
+                self.vram[offset] = value;
             }
 
             // OAM
@@ -331,28 +530,72 @@ impl Ppu {
             }
 
             0xff40 => self.lcdc = value,
+            0xff41 => self.stat = value & 0x78,
             0xff42 => self.scy = value,
             0xff43 => self.scx = value,
-            0xff44 => self.ly = value,
+            // LY is read only
+            0xff44 => {}
+            0xff45 => self.lyc = value,
             0xff47 => self.bgp = value,
+            0xff48 => self.obp0 = value,
+            0xff49 => self.obp1 = value,
             0xff4a => self.wy = value,
             0xff4b => self.wx = value,
 
-            // TODO:
-            0xff41 => {
-                println!("stat write {:X}", value);
-            }
-            0xff45 => {
-                println!("lyc write {:X}", value);
-            }
-            0xff48 => {
-                println!("obp0 write {:X}", value);
+            // CGB registers
+            0xff4f | 0xff68..=0xff6b if !self.cgb => {}
+            0xff4f => self.vbk = value & 0x01,
+            0xff68 => self.bcps = value & 0xbf,
+            0xff69 => {
+                self.bg_palette[(self.bcps & 0x3f) as usize] = value;
+                self.bcps = increment_palette_index(self.bcps);
             }
-            0xff49 => {
-                println!("obp1 write {:X}", value);
+            0xff6a => self.ocps = value & 0xbf,
+            0xff6b => {
+                self.obj_palette[(self.ocps & 0x3f) as usize] = value;
+                self.ocps = increment_palette_index(self.ocps);
             }
 
             _ => panic!("unexpected address #{:x}", address),
         }
     }
 }
+
+fn increment_palette_index(index: u8) -> u8 {
+    match index & 0x80 {
+        0 => index,
+        _ => 0x80 | (index.wrapping_add(1) & 0x3f),
+    }
+}
+
+// Bit 0-4   Red Intensity   (00-1F)
+// Bit 5-9   Green Intensity (00-1F)
+// Bit 10-14 Blue Intensity  (00-1F)
+pub(crate) fn rgb555_to_rgb888(color: u16) -> u32 {
+    let scale = |c: u16| {
+        let c = (c & 0x1f) as u32;
+        (c << 3) | (c >> 2)
+    };
+
+    0xFF000000 | (scale(color) << 16) | (scale(color >> 5) << 8) | scale(color >> 10)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{rgb555_to_rgb888, Ppu};
+
+    #[test]
+    fn test_cgb_palette() {
+        let mut ppu = Ppu::new(true);
+
+        // auto increment from palette 1 color 0
+        ppu.write(0xff68, 0x88);
+        ppu.write(0xff69, 0x1f);
+        ppu.write(0xff69, 0x00);
+        assert_eq!(ppu.read(0xff68), 0xca);
+
+        assert_eq!(ppu.cgb_color_to_rgb(1, 0), 0xFFFF0000);
+        assert_eq!(rgb555_to_rgb888(0x7fff), 0xFFFFFFFF);
+        assert_eq!(rgb555_to_rgb888(0x03e0), 0xFF00FF00);
+    }
+}
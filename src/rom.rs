@@ -0,0 +1,247 @@
+// Loads ROMs from files or memory, unpacking zip and gzip archives.
+//
+// Archives are recognized by their content, not their file name. From a zip file,
+// the first .gb or .gbc file is loaded.
+//
+// A patch with the same name as the ROM file (game.ips for game.gb) is applied to it.
+
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+use flate2::read::{DeflateDecoder, GzDecoder};
+use flate2::Crc;
+
+use crate::patch;
+
+// ref. https://pkware.cachefly.net/webdocs/casestudies/APPNOTE.TXT
+const ZIP_LOCAL_HEADER: [u8; 4] = [0x50, 0x4b, 0x03, 0x04];
+const ZIP_CENTRAL_HEADER: [u8; 4] = [0x50, 0x4b, 0x01, 0x02];
+const ZIP_END_OF_CENTRAL_DIRECTORY: [u8; 4] = [0x50, 0x4b, 0x05, 0x06];
+// ref. https://www.rfc-editor.org/rfc/rfc1952
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+// The largest cartridges are 8MB, like the patched ROMs. Larger archives are zip bombs.
+const MAX_ROM_SIZE: usize = patch::MAX_TARGET_SIZE;
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Reads the ROM in the file at `path`, a ROM or a zip / gzip archive, with the patch
+/// next to it applied.
+pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Vec<u8>> {
+    load_with_patch(path, None)
+}
+
+/// Reads the ROM in the file at `path` with the IPS / UPS / BPS patch at `patch` applied,
+/// or the patch next to the ROM when `patch` is None.
+pub fn load_with_patch<P: AsRef<Path>>(path: P, patch: Option<&Path>) -> io::Result<Vec<u8>> {
+    let rom = unpack(&fs::read(&path)?)?;
+
+    match patch.map(Path::to_path_buf).or_else(|| patch::find(&path)) {
+        Some(patch) => patch::apply(&rom, &fs::read(&patch)?).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", patch.display(), e),
+            )
+        }),
+        None => Ok(rom),
+    }
+}
+
+/// The ROM in `data`, a ROM or a zip / gzip archive.
+pub fn unpack(data: &[u8]) -> io::Result<Vec<u8>> {
+    if data.starts_with(&ZIP_LOCAL_HEADER) {
+        unzip(data)
+    } else if data.starts_with(&GZIP_MAGIC) {
+        inflate(GzDecoder::new(data))
+    } else {
+        Ok(data.to_vec())
+    }
+}
+
+// Decompresses up to MAX_ROM_SIZE bytes.
+fn inflate<R: Read>(decoder: R) -> io::Result<Vec<u8>> {
+    let mut rom = vec![];
+    decoder
+        .take(MAX_ROM_SIZE as u64 + 1)
+        .read_to_end(&mut rom)?;
+    match rom.len() {
+        0..=MAX_ROM_SIZE => Ok(rom),
+        _ => Err(invalid_data("the ROM in the archive is larger than 8MB")),
+    }
+}
+
+fn bytes(data: &[u8], offset: usize, len: usize) -> io::Result<&[u8]> {
+    data.get(offset..offset + len)
+        .ok_or_else(|| invalid_data("truncated zip file"))
+}
+
+fn u16_at(data: &[u8], offset: usize) -> io::Result<usize> {
+    let b = bytes(data, offset, 2)?;
+    Ok(u16::from_le_bytes([b[0], b[1]]) as usize)
+}
+
+fn u32_at(data: &[u8], offset: usize) -> io::Result<usize> {
+    let b = bytes(data, offset, 4)?;
+    Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+}
+
+fn is_rom_name(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    name.ends_with(".gb") || name.ends_with(".gbc")
+}
+
+// The first ROM in the central directory, the list of the files at the end of the archive.
+fn unzip(data: &[u8]) -> io::Result<Vec<u8>> {
+    // the end of central directory record is followed by a comment of up to 64KB
+    let end = (0..=data.len().saturating_sub(22))
+        .rev()
+        .find(|i| data[*i..].starts_with(&ZIP_END_OF_CENTRAL_DIRECTORY))
+        .ok_or_else(|| invalid_data("no zip central directory"))?;
+    let entries = u16_at(data, end + 10)?;
+    let mut offset = u32_at(data, end + 16)?;
+
+    for _ in 0..entries {
+        if bytes(data, offset, 4)? != ZIP_CENTRAL_HEADER {
+            return Err(invalid_data("invalid zip central directory"));
+        }
+        let method = u16_at(data, offset + 10)?;
+        let crc = u32_at(data, offset + 16)? as u32;
+        let compressed_size = u32_at(data, offset + 20)?;
+        let size = u32_at(data, offset + 24)?;
+        let name_len = u16_at(data, offset + 28)?;
+        let extra_len = u16_at(data, offset + 30)?;
+        let comment_len = u16_at(data, offset + 32)?;
+        let local_offset = u32_at(data, offset + 42)?;
+        let name = String::from_utf8_lossy(bytes(data, offset + 46, name_len)?);
+
+        if is_rom_name(&name) {
+            if size > MAX_ROM_SIZE {
+                return Err(invalid_data("the ROM in the zip is larger than 8MB"));
+            }
+
+            // the data follows the local header, its name and extra field
+            if bytes(data, local_offset, 4)? != ZIP_LOCAL_HEADER {
+                return Err(invalid_data("invalid zip local header"));
+            }
+            let start = local_offset
+                + 30
+                + u16_at(data, local_offset + 26)?
+                + u16_at(data, local_offset + 28)?;
+            let compressed = bytes(data, start, compressed_size)?;
+
+            let rom = match method {
+                // stored
+                0 => compressed.to_vec(),
+                // deflated
+                8 => inflate(DeflateDecoder::new(compressed))?,
+                _ => return Err(invalid_data("unsupported zip compression method")),
+            };
+
+            let mut computed = Crc::new();
+            computed.update(&rom);
+            if rom.len() != size || computed.sum() != crc {
+                return Err(invalid_data("corrupted file in the zip"));
+            }
+
+            return Ok(rom);
+        }
+
+        offset += 46 + name_len + extra_len + comment_len;
+    }
+
+    Err(invalid_data("no .gb or .gbc file in the zip"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{unpack, MAX_ROM_SIZE};
+    use flate2::write::{DeflateEncoder, GzEncoder};
+    use flate2::{Compression, Crc};
+    use std::io::Write;
+
+    // zip of deflated files
+    fn zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut data = vec![];
+        let mut central = vec![];
+
+        for (name, content) in files.iter() {
+            let mut encoder = DeflateEncoder::new(vec![], Compression::default());
+            encoder.write_all(content).unwrap();
+            let compressed = encoder.finish().unwrap();
+            let mut crc = Crc::new();
+            crc.update(content);
+
+            let mut fields = vec![];
+            fields.extend_from_slice(&8u16.to_le_bytes());
+            fields.extend_from_slice(&[0; 4]);
+            fields.extend_from_slice(&crc.sum().to_le_bytes());
+            fields.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+            fields.extend_from_slice(&(content.len() as u32).to_le_bytes());
+            fields.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            fields.extend_from_slice(&[0; 2]);
+
+            central.extend_from_slice(&[0x50, 0x4b, 0x01, 0x02, 20, 0, 20, 0, 0, 0]);
+            central.extend_from_slice(&fields);
+            // comment length, disk, attributes
+            central.extend_from_slice(&[0; 10]);
+            central.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            central.extend_from_slice(name.as_bytes());
+
+            data.extend_from_slice(&[0x50, 0x4b, 0x03, 0x04, 20, 0, 0, 0]);
+            data.extend_from_slice(&fields);
+            data.extend_from_slice(name.as_bytes());
+            data.extend_from_slice(&compressed);
+        }
+
+        let central_offset = data.len() as u32;
+        data.extend_from_slice(&central);
+        data.extend_from_slice(&[0x50, 0x4b, 0x05, 0x06, 0, 0, 0, 0]);
+        data.extend_from_slice(&(files.len() as u16).to_le_bytes());
+        data.extend_from_slice(&(files.len() as u16).to_le_bytes());
+        data.extend_from_slice(&(central.len() as u32).to_le_bytes());
+        data.extend_from_slice(&central_offset.to_le_bytes());
+        data.extend_from_slice(&[0; 2]);
+        data
+    }
+
+    #[test]
+    fn test_unpack() {
+        let rom: Vec<u8> = (0..0x8000).map(|i| (i % 251) as u8).collect();
+        assert_eq!(unpack(&rom).unwrap(), rom);
+
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(&rom).unwrap();
+        assert_eq!(unpack(&encoder.finish().unwrap()).unwrap(), rom);
+
+        let archive = zip(&[("readme.txt", b"hello"), ("GAME.GBC", &rom)]);
+        assert_eq!(unpack(&archive).unwrap(), rom);
+
+        assert!(unpack(&zip(&[("readme.txt", b"hello")])).is_err());
+        let mut corrupted = archive.clone();
+        let last = corrupted.len() - 200;
+        corrupted[last] ^= 0xff;
+        assert!(unpack(&corrupted).is_err());
+    }
+
+    #[test]
+    fn test_unpack_bomb() {
+        let bomb = vec![0; MAX_ROM_SIZE + 1];
+
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(&bomb).unwrap();
+        assert!(unpack(&encoder.finish().unwrap()).is_err());
+
+        // rejected by the size in the central directory
+        assert!(unpack(&zip(&[("BOMB.GB", &bomb)])).is_err());
+
+        // and by the decompressed size when the central directory lies
+        let mut archive = zip(&[("BOMB.GB", &bomb)]);
+        let central = archive.len() - 22 - 46 - 7;
+        archive[central + 24..central + 28].copy_from_slice(&0x8000u32.to_le_bytes());
+        let error = unpack(&archive).unwrap_err();
+        assert!(error.to_string().contains("larger than 8MB"));
+    }
+}
@@ -0,0 +1,292 @@
+// ref. https://gbdev.io/pandocs/Serial_Data_Transfer_(Link_Cable).html
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+pub mod printer;
+pub mod tcp;
+
+// 8192Hz internal clock -> 1 bit every 512 clocks
+const CLOCKS_PER_BIT: usize = 512;
+// CGB: 262144Hz internal clock -> 1 bit every 16 clocks
+const CLOCKS_PER_BIT_FAST: usize = 16;
+// bytes kept by a console, the older half is dropped beyond that
+const CONSOLE_OUTPUT_LIMIT: usize = 64 * 1024;
+
+/// The other end of the link cable.
+pub trait LinkCable {
+    /// Called when this side (internal clock) has shifted out `data`.
+    /// Returns the byte shifted in from the peer (0xFF when nothing is connected).
+    fn send(&mut self, data: u8) -> u8;
+
+    /// Polled while this side waits for the peer's clock (external clock).
+    /// `data` is the byte that will be shifted out to the peer.
+    /// Returns the received byte once the peer has clocked a transfer.
+    fn receive(&mut self, data: u8) -> Option<u8>;
+
+    /// Advances the peer's notion of time by `tick` clocks.
+    /// Called every step whether or not a transfer is in progress.
+    fn step(&mut self, _tick: usize) {}
+}
+
+pub struct Serial {
+    // $FF01 - SB - Serial transfer data
+    sb: u8,
+    // $FF02 - SC - Serial Transfer Control
+    // Bit 7 - Transfer Start Flag (0=No transfer is in progress or requested, 1=Transfer in progress, or requested)
+    // Bit 1 - Clock Speed (0=Normal, 1=Fast) ** CGB Mode Only **
+    // Bit 0 - Shift Clock (0=External Clock, 1=Internal Clock)
+    sc: u8,
+    cgb: bool,
+    clocks: usize,
+    // number of bits shifted in the current transfer
+    bits: u8,
+    link: Box<dyn LinkCable>,
+    pub irq: bool,
+}
+
+impl Default for Serial {
+    fn default() -> Self {
+        Serial::new(Box::new(Console::default()))
+    }
+}
+
+impl Serial {
+    pub fn new(link: Box<dyn LinkCable>) -> Self {
+        Serial {
+            sb: 0,
+            sc: 0,
+            cgb: false,
+            clocks: 0,
+            bits: 0,
+            link,
+            irq: false,
+        }
+    }
+
+    pub fn new_with_model(cgb: bool) -> Self {
+        Serial {
+            cgb,
+            ..Serial::default()
+        }
+    }
+
+    /// Plug another peer into the link port.
+    pub fn connect(&mut self, link: Box<dyn LinkCable>) {
+        self.link = link;
+    }
+
+    pub fn write_byte(&mut self, address: u16, value: u8) {
+        match address {
+            0xff01 => self.sb = value,
+            0xff02 => {
+                self.sc = match self.cgb {
+                    true => value & 0x83,
+                    false => value & 0x81,
+                };
+                // (re)start the transfer
+                self.clocks = 0;
+                self.bits = 0;
+            }
+            _ => panic!("unexpected address #{:X}", address),
+        }
+    }
+
+    pub fn read_byte(&self, address: u16) -> u8 {
+        match address {
+            0xff01 => self.sb,
+            // unused bits read as 1
+            0xff02 if self.cgb => self.sc | 0x7c,
+            0xff02 => self.sc | 0x7e,
+            _ => panic!("unexpected address #{:X}", address),
+        }
+    }
+
+    pub fn step(&mut self, tick: usize) {
+        self.link.step(tick);
+
+        // no transfer in progress
+        if self.sc & 0x80 == 0 {
+            return;
+        }
+
+        if self.sc & 0x01 == 0 {
+            // External Clock: the peer drives the transfer
+            if let Some(received) = self.link.receive(self.sb) {
+                self.complete(received);
+            }
+            return;
+        }
+
+        // Internal Clock
+        let clocks_per_bit = match self.sc & 0x02 {
+            0 => CLOCKS_PER_BIT,
+            _ => CLOCKS_PER_BIT_FAST,
+        };
+        self.clocks += tick;
+        while self.clocks >= clocks_per_bit && self.bits < 8 {
+            self.clocks -= clocks_per_bit;
+            self.bits += 1;
+        }
+
+        if self.bits == 8 {
+            let received = self.link.send(self.sb);
+            self.complete(received);
+        }
+    }
+
+    fn complete(&mut self, received: u8) {
+        self.sb = received;
+        // Bit 7 is cleared and the serial interrupt is requested when the transfer is done
+        self.sc &= 0x7f;
+        self.clocks = 0;
+        self.bits = 0;
+        self.irq = true;
+    }
+}
+
+/// Records the bytes sent over the link, like a terminal on the other end.
+/// Test ROMs (e.g. blargg's) report their results this way.
+#[derive(Default)]
+pub struct Console {
+    output: Rc<RefCell<String>>,
+    // also print the output to stdout
+    print: bool,
+}
+
+impl Console {
+    /// A console which also prints the output to stdout.
+    pub fn printing() -> Self {
+        Console {
+            print: true,
+            ..Console::default()
+        }
+    }
+
+    /// Handle to the output received so far, the last 32KB to 64KB of it.
+    pub fn output(&self) -> Rc<RefCell<String>> {
+        Rc::clone(&self.output)
+    }
+}
+
+impl LinkCable for Console {
+    fn send(&mut self, data: u8) -> u8 {
+        if self.print {
+            print!("{}", data as char);
+        }
+
+        let mut output = self.output.borrow_mut();
+        output.push(data as char);
+        if output.len() > CONSOLE_OUTPUT_LIMIT {
+            let start = (output.len() - CONSOLE_OUTPUT_LIMIT / 2..)
+                .find(|i| output.is_char_boundary(*i))
+                .unwrap();
+            output.drain(..start);
+        }
+
+        // nothing is driving the data line
+        0xff
+    }
+
+    fn receive(&mut self, _data: u8) -> Option<u8> {
+        // a console never clocks a transfer
+        None
+    }
+}
+
+#[derive(Default)]
+struct Wire {
+    // byte the waiting (external clock) side will shift out
+    ready: Option<u8>,
+    // bytes clocked in by the peer, not yet picked up
+    inbox: VecDeque<u8>,
+}
+
+/// One end of a cable created by `cable()`.
+pub struct Plug {
+    this: Rc<RefCell<Wire>>,
+    peer: Rc<RefCell<Wire>>,
+}
+
+/// Creates a link cable connecting two emulator instances running in the same thread.
+pub fn cable() -> (Plug, Plug) {
+    let a = Rc::new(RefCell::new(Wire::default()));
+    let b = Rc::new(RefCell::new(Wire::default()));
+
+    (
+        Plug {
+            this: Rc::clone(&a),
+            peer: Rc::clone(&b),
+        },
+        Plug { this: b, peer: a },
+    )
+}
+
+impl LinkCable for Plug {
+    fn send(&mut self, data: u8) -> u8 {
+        let mut peer = self.peer.borrow_mut();
+        match peer.ready.take() {
+            Some(received) => {
+                peer.inbox.push_back(data);
+                received
+            }
+            // the peer is not waiting for a transfer
+            None => 0xff,
+        }
+    }
+
+    fn receive(&mut self, data: u8) -> Option<u8> {
+        let mut this = self.this.borrow_mut();
+        match this.inbox.pop_front() {
+            Some(received) => Some(received),
+            None => {
+                this.ready = Some(data);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cable, Console, LinkCable, Serial, CONSOLE_OUTPUT_LIMIT};
+
+    #[test]
+    fn test_console_limit() {
+        let mut console = Console::default();
+        let output = console.output();
+        for i in 0..CONSOLE_OUTPUT_LIMIT + 1 {
+            console.send(b'0' + (i % 10) as u8);
+        }
+        console.send(0xe9);
+        assert!(output.borrow().len() <= CONSOLE_OUTPUT_LIMIT);
+        assert!(output.borrow().ends_with("6\u{e9}"));
+    }
+
+    #[test]
+    fn test_cable_transfer() {
+        let (a, b) = cable();
+        let mut master = Serial::new(Box::new(a));
+        let mut slave = Serial::new(Box::new(b));
+
+        slave.write_byte(0xff01, 0x42);
+        slave.write_byte(0xff02, 0x80);
+        master.write_byte(0xff01, 0x99);
+        master.write_byte(0xff02, 0x81);
+
+        // 8 bits * 512 clocks
+        for _ in 0..(8 * 512 / 4) {
+            slave.step(4);
+            master.step(4);
+        }
+        slave.step(4);
+
+        assert_eq!(master.read_byte(0xff01), 0x42);
+        assert_eq!(slave.read_byte(0xff01), 0x99);
+        assert_eq!(master.read_byte(0xff02) & 0x80, 0);
+        assert_eq!(slave.read_byte(0xff02) & 0x80, 0);
+        assert!(master.irq);
+        assert!(slave.irq);
+    }
+}
@@ -0,0 +1,396 @@
+// ref. https://gbdev.io/pandocs/Gameboy_Printer.html
+
+use std::path::PathBuf;
+
+use crate::image;
+use crate::serial::LinkCable;
+
+// Commands
+const INIT: u8 = 0x01;
+const PRINT: u8 = 0x02;
+const DATA: u8 = 0x04;
+const STATUS: u8 = 0x0f;
+
+// Status
+// Bit 0: Checksum error
+// Bit 1: Printer busy
+// Bit 2: Image data full
+// Bit 3: Unprocessed data
+const STATUS_CHECKSUM_ERROR: u8 = 0x01;
+const STATUS_BUSY: u8 = 0x02;
+const STATUS_FULL: u8 = 0x04;
+const STATUS_UNPROCESSED: u8 = 0x08;
+
+// the printer replies 0x81 to the first byte after the checksum
+const DEVICE_ID: u8 = 0x81;
+
+// 2 rows of 20 tiles (160 x 16 pixels) per data packet
+const BYTES_PER_BAND: usize = 20 * 2 * 16;
+// the printer holds up to 9 bands (160 x 144 pixels)
+const MAX_BANDS: usize = 9;
+
+const WIDTH: usize = 160;
+// pixel rows fed per margin unit
+const MARGIN_LINES: usize = 8;
+
+// number of status packets answered busy after a print, so games see the printer working
+const BUSY_STATUS_COUNT: u8 = 2;
+
+// 4 shades of the thermal paper
+const SHADES: [u32; 4] = [0xFFFFFFFF, 0xFFAAAAAA, 0xFF555555, 0xFF000000];
+
+enum State {
+    Magic1,
+    Magic2,
+    Command,
+    Compression,
+    LengthLow,
+    LengthHigh,
+    Data,
+    ChecksumLow,
+    ChecksumHigh,
+    Alive,
+    Status,
+}
+
+/// Game Boy Printer attached to the link port.
+/// Each printed image is written to `directory` as a PNG file.
+pub struct Printer {
+    directory: PathBuf,
+    state: State,
+
+    // packet being received
+    command: u8,
+    compressed: bool,
+    length: usize,
+    data: Vec<u8>,
+    checksum: u16,
+
+    // decompressed image data waiting to be printed
+    image: Vec<u8>,
+    status: u8,
+    busy: u8,
+    printed: usize,
+}
+
+impl Printer {
+    pub fn new<P: Into<PathBuf>>(directory: P) -> Self {
+        Printer {
+            directory: directory.into(),
+            state: State::Magic1,
+            command: 0,
+            compressed: false,
+            length: 0,
+            data: vec![],
+            checksum: 0,
+            image: vec![],
+            status: 0,
+            busy: 0,
+            printed: 0,
+        }
+    }
+
+    /// Number of images printed so far.
+    pub fn printed(&self) -> usize {
+        self.printed
+    }
+
+    fn receive_byte(&mut self, value: u8) -> u8 {
+        match self.state {
+            State::Magic1 => {
+                if value == 0x88 {
+                    self.state = State::Magic2;
+                }
+            }
+            State::Magic2 => {
+                self.state = match value {
+                    0x33 => State::Command,
+                    0x88 => State::Magic2,
+                    _ => State::Magic1,
+                };
+            }
+            State::Command => {
+                self.command = value;
+                self.checksum = value as u16;
+                self.state = State::Compression;
+            }
+            State::Compression => {
+                self.compressed = value & 0x01 != 0;
+                self.checksum = self.checksum.wrapping_add(value as u16);
+                self.state = State::LengthLow;
+            }
+            State::LengthLow => {
+                self.length = value as usize;
+                self.checksum = self.checksum.wrapping_add(value as u16);
+                self.state = State::LengthHigh;
+            }
+            State::LengthHigh => {
+                self.length |= (value as usize) << 8;
+                self.checksum = self.checksum.wrapping_add(value as u16);
+                self.data.clear();
+                self.state = match self.length {
+                    0 => State::ChecksumLow,
+                    _ => State::Data,
+                };
+            }
+            State::Data => {
+                self.data.push(value);
+                self.checksum = self.checksum.wrapping_add(value as u16);
+                if self.data.len() == self.length {
+                    self.state = State::ChecksumLow;
+                }
+            }
+            State::ChecksumLow => {
+                self.checksum ^= value as u16;
+                self.state = State::ChecksumHigh;
+            }
+            State::ChecksumHigh => {
+                self.checksum ^= (value as u16) << 8;
+                self.state = State::Alive;
+            }
+            State::Alive => {
+                self.state = State::Status;
+                return DEVICE_ID;
+            }
+            State::Status => {
+                self.state = State::Magic1;
+                // the status is answered before the packet is handled
+                let status = self.status();
+                self.execute();
+                return status;
+            }
+        }
+
+        0x00
+    }
+
+    fn status(&mut self) -> u8 {
+        let mut status = self.status;
+        if self.busy > 0 {
+            self.busy -= 1;
+            status |= STATUS_BUSY;
+        }
+
+        status
+    }
+
+    fn execute(&mut self) {
+        // checksum bytes were xor-ed into the sum, so a valid packet leaves 0
+        if self.checksum != 0 {
+            self.status |= STATUS_CHECKSUM_ERROR;
+            return;
+        }
+        self.status &= !STATUS_CHECKSUM_ERROR;
+
+        match self.command {
+            INIT => {
+                self.image.clear();
+                self.status = 0;
+                self.busy = 0;
+            }
+            DATA => {
+                let data = match self.compressed {
+                    true => decompress(&self.data),
+                    false => self.data.clone(),
+                };
+                if self.image.len() + data.len() <= BYTES_PER_BAND * MAX_BANDS {
+                    self.image.extend(data);
+                }
+
+                if !self.image.is_empty() {
+                    self.status |= STATUS_UNPROCESSED;
+                }
+                if self.image.len() >= BYTES_PER_BAND * MAX_BANDS {
+                    self.status |= STATUS_FULL;
+                }
+            }
+            PRINT => {
+                if self.data.len() < 4 {
+                    return;
+                }
+                // Byte 0: Number of sheets
+                // Byte 1: Margins (upper nibble: before, lower nibble: after)
+                // Byte 2: Palette
+                // Byte 3: Exposure
+                let sheets = self.data[0];
+                let margin_before = (self.data[1] >> 4) as usize;
+                let margin_after = (self.data[1] & 0x0f) as usize;
+                let palette = self.data[2];
+
+                // 0 sheets only feeds the paper, the image is kept
+                if sheets == 0 {
+                    self.busy = BUSY_STATUS_COUNT;
+                    return;
+                }
+                // a PNG file per sheet
+                for _ in 0..sheets {
+                    self.print(margin_before, margin_after, palette);
+                }
+                self.image.clear();
+                self.status &= !(STATUS_UNPROCESSED | STATUS_FULL);
+                self.busy = BUSY_STATUS_COUNT;
+            }
+            STATUS => {}
+            _ => {}
+        }
+    }
+
+    fn print(&mut self, margin_before: usize, margin_after: usize, palette: u8) {
+        // palette 0x00 is treated as the default 0xE4
+        let palette = match palette {
+            0x00 => 0xe4,
+            _ => palette,
+        };
+
+        let bands = self.image.len() / BYTES_PER_BAND;
+        let top = margin_before * MARGIN_LINES;
+        let height = top + bands * 16 + margin_after * MARGIN_LINES;
+        if height == 0 {
+            return;
+        }
+
+        let mut buffer = vec![SHADES[0]; WIDTH * height];
+
+        for band in 0..bands {
+            for tile in 0..40 {
+                // 20 tiles per row, 16 bytes per tile
+                let base = band * BYTES_PER_BAND + tile * 16;
+                let tile_x = (tile % 20) * 8;
+                let tile_y = top + band * 16 + (tile / 20) * 8;
+
+                for offset_y in 0..8 {
+                    let low = self.image[base + offset_y * 2];
+                    let high = self.image[base + offset_y * 2 + 1];
+
+                    for offset_x in 0..8 {
+                        let mask = 1 << (7 - offset_x);
+                        let color_no = ((high & mask != 0) as u8) << 1 | (low & mask != 0) as u8;
+                        let shade = (palette >> (color_no * 2)) & 0x03;
+
+                        buffer[(tile_y + offset_y) * WIDTH + tile_x + offset_x] =
+                            SHADES[shade as usize];
+                    }
+                }
+            }
+        }
+
+        let path = self
+            .directory
+            .join(format!("print_{:03}.png", self.printed));
+        match image::write_png(&path, WIDTH, height, &buffer) {
+            Ok(_) => self.printed += 1,
+            Err(e) => eprintln!("failed to write {}: {}", path.display(), e),
+        }
+    }
+}
+
+// Run-length encoding
+// 0x00-0x7F: the next N+1 bytes are copied as is
+// 0x80-0xFF: the next byte is repeated (N & 0x7F)+2 times
+fn decompress(data: &[u8]) -> Vec<u8> {
+    let mut output = vec![];
+    let mut i = 0;
+
+    while i < data.len() {
+        let control = data[i];
+        i += 1;
+
+        if control & 0x80 != 0 {
+            let length = (control & 0x7f) as usize + 2;
+            if let Some(value) = data.get(i) {
+                output.resize(output.len() + length, *value);
+            }
+            i += 1;
+        } else {
+            let length = control as usize + 1;
+            let end = (i + length).min(data.len());
+            output.extend_from_slice(&data[i..end]);
+            i = end;
+        }
+    }
+
+    output
+}
+
+impl LinkCable for Printer {
+    fn send(&mut self, data: u8) -> u8 {
+        self.receive_byte(data)
+    }
+
+    fn receive(&mut self, _data: u8) -> Option<u8> {
+        // the Game Boy always provides the clock
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decompress, Printer, BYTES_PER_BAND};
+    use crate::serial::LinkCable;
+    use std::fs;
+
+    fn send_packet(printer: &mut Printer, command: u8, data: &[u8]) -> (u8, u8) {
+        let mut packet = vec![command, 0x00, data.len() as u8, (data.len() >> 8) as u8];
+        packet.extend_from_slice(data);
+        let checksum = packet
+            .iter()
+            .fold(0u16, |sum, v| sum.wrapping_add(*v as u16));
+
+        printer.send(0x88);
+        printer.send(0x33);
+        for v in packet.iter() {
+            assert_eq!(printer.send(*v), 0x00);
+        }
+        printer.send(checksum as u8);
+        printer.send((checksum >> 8) as u8);
+
+        (printer.send(0x00), printer.send(0x00))
+    }
+
+    #[test]
+    fn test_decompress() {
+        assert_eq!(
+            decompress(&[0x81, 0xaa, 0x01, 0x12, 0x34]),
+            vec![0xaa, 0xaa, 0xaa, 0x12, 0x34]
+        );
+    }
+
+    #[test]
+    fn test_print() {
+        let directory =
+            std::env::temp_dir().join(format!("gbrust_test_print_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        let mut printer = Printer::new(&directory);
+
+        assert_eq!(send_packet(&mut printer, 0x01, &[]), (0x81, 0x00));
+        assert_eq!(
+            send_packet(&mut printer, 0x04, &[0xff; BYTES_PER_BAND]),
+            (0x81, 0x00)
+        );
+        // unprocessed data
+        assert_eq!(send_packet(&mut printer, 0x0f, &[]), (0x81, 0x08));
+        // 1 sheet, no margins, default palette, default exposure
+        send_packet(&mut printer, 0x02, &[0x01, 0x00, 0xe4, 0x40]);
+        // busy
+        assert_eq!(send_packet(&mut printer, 0x0f, &[]), (0x81, 0x02));
+
+        assert_eq!(printer.printed(), 1);
+        let decoder = png::Decoder::new(fs::File::open(directory.join("print_000.png")).unwrap());
+        let reader = decoder.read_info().unwrap();
+        assert_eq!(reader.info().width, 160);
+        assert_eq!(reader.info().height, 16);
+
+        // 0 sheets: paper feed only, then 2 sheets of the same image
+        send_packet(&mut printer, 0x01, &[]);
+        send_packet(&mut printer, 0x04, &[0xff; BYTES_PER_BAND]);
+        send_packet(&mut printer, 0x02, &[0x00, 0x00, 0xe4, 0x40]);
+        assert_eq!(printer.printed(), 1);
+        assert_eq!(send_packet(&mut printer, 0x0f, &[]), (0x81, 0x0a));
+        send_packet(&mut printer, 0x02, &[0x02, 0x00, 0xe4, 0x40]);
+        assert_eq!(printer.printed(), 3);
+        assert!(directory.join("print_002.png").is_file());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,191 @@
+use std::collections::VecDeque;
+use std::io;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+
+use crate::serial::LinkCable;
+
+// Both machines run in slices of SYNC_CLOCKS and exchange one packet at the end of every slice.
+// A side only ever acts on what it learned at the last sync point,
+// so the outcome of a transfer does not depend on how fast each process runs.
+// One slice is the duration of a whole byte transfer (8 bits * 512 clocks).
+const SYNC_CLOCKS: usize = 8 * 512;
+
+// Packet
+// Byte 0: 1 if this side is waiting for the peer's clock (external clock), 0 otherwise
+// Byte 1: the byte that will be shifted out to the peer when it clocks a transfer
+// Byte 2: number of transfers this side clocked during the slice (N)
+// Byte 3..3+N: the bytes shifted out by those transfers
+
+/// Link cable to another gbrust process over TCP.
+pub struct TcpLink {
+    stream: Option<TcpStream>,
+    clocks: usize,
+    // what we are going to tell the peer at the next sync point
+    ready: Option<u8>,
+    sent: Vec<u8>,
+    // what the peer told us at the last sync point
+    peer_ready: Option<u8>,
+    inbox: VecDeque<u8>,
+}
+
+impl TcpLink {
+    pub fn new(stream: TcpStream) -> io::Result<Self> {
+        // packets are tiny and latency bound
+        stream.set_nodelay(true)?;
+
+        Ok(TcpLink {
+            stream: Some(stream),
+            clocks: 0,
+            ready: None,
+            sent: vec![],
+            peer_ready: None,
+            inbox: VecDeque::new(),
+        })
+    }
+
+    /// Waits for the other process to connect to `address`,
+    /// e.g. ("127.0.0.1", port) on this machine or ("0.0.0.0", port) for LAN play.
+    pub fn listen<A: ToSocketAddrs>(address: A) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        let (stream, _) = listener.accept()?;
+
+        TcpLink::new(stream)
+    }
+
+    pub fn connect<A: ToSocketAddrs>(address: A) -> io::Result<Self> {
+        TcpLink::new(TcpStream::connect(address)?)
+    }
+
+    fn sync(&mut self) {
+        let stream = match self.stream.as_mut() {
+            Some(stream) => stream,
+            None => return,
+        };
+
+        let mut packet = vec![
+            self.ready.is_some() as u8,
+            self.ready.unwrap_or(0xff),
+            self.sent.len() as u8,
+        ];
+        packet.extend_from_slice(&self.sent);
+
+        match exchange(stream, &packet) {
+            Ok((peer_ready, received)) => {
+                self.peer_ready = peer_ready;
+                self.inbox.extend(received);
+            }
+            Err(e) => {
+                // behave as if the cable was pulled out
+                eprintln!("link cable disconnected: {}", e);
+                self.stream = None;
+                self.peer_ready = None;
+            }
+        }
+
+        self.ready = None;
+        self.sent.clear();
+    }
+}
+
+fn exchange(stream: &mut TcpStream, packet: &[u8]) -> io::Result<(Option<u8>, Vec<u8>)> {
+    stream.write_all(packet)?;
+    stream.flush()?;
+
+    let mut header = [0; 3];
+    stream.read_exact(&mut header)?;
+
+    let mut received = vec![0; header[2] as usize];
+    stream.read_exact(&mut received)?;
+
+    let peer_ready = match header[0] {
+        0 => None,
+        _ => Some(header[1]),
+    };
+
+    Ok((peer_ready, received))
+}
+
+impl LinkCable for TcpLink {
+    fn send(&mut self, data: u8) -> u8 {
+        if self.stream.is_none() {
+            return 0xff;
+        }
+
+        match self.peer_ready.take() {
+            Some(received) => {
+                self.sent.push(data);
+                received
+            }
+            // the peer is not waiting for a transfer
+            None => 0xff,
+        }
+    }
+
+    fn receive(&mut self, data: u8) -> Option<u8> {
+        match self.inbox.pop_front() {
+            Some(received) => Some(received),
+            None => {
+                self.ready = Some(data);
+                None
+            }
+        }
+    }
+
+    fn step(&mut self, tick: usize) {
+        self.clocks += tick;
+        while self.clocks >= SYNC_CLOCKS {
+            self.clocks -= SYNC_CLOCKS;
+            self.sync();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TcpLink;
+    use crate::serial::Serial;
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn run(serial: &mut Serial, clocks: usize) {
+        for _ in 0..(clocks / 4) {
+            serial.step(4);
+        }
+    }
+
+    #[test]
+    fn test_tcp_link_loopback() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let slave = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut serial = Serial::new(Box::new(TcpLink::new(stream).unwrap()));
+
+            serial.write_byte(0xff01, 0x42);
+            serial.write_byte(0xff02, 0x80);
+            run(&mut serial, 4 * 4096);
+
+            (
+                serial.read_byte(0xff01),
+                serial.read_byte(0xff02),
+                serial.irq,
+            )
+        });
+
+        let mut serial = Serial::new(Box::new(TcpLink::connect(address).unwrap()));
+        serial.write_byte(0xff01, 0x99);
+        serial.write_byte(0xff02, 0x81);
+        run(&mut serial, 4 * 4096);
+
+        assert_eq!(serial.read_byte(0xff01), 0x42);
+        assert_eq!(serial.read_byte(0xff02) & 0x80, 0);
+        assert!(serial.irq);
+
+        let (sb, sc, irq) = slave.join().unwrap();
+        assert_eq!(sb, 0x99);
+        assert_eq!(sc & 0x80, 0);
+        assert!(irq);
+    }
+}
@@ -0,0 +1,498 @@
+// Super Game Boy
+// ref. https://gbdev.io/pandocs/SGB_Functions.html
+//
+// The game sends 16 byte command packets to the SNES bit by bit through the joypad register.
+// The SNES draws the Game Boy screen in the middle of a 256x224 picture with a border around it,
+// and colors it with 4 palettes assigned to areas of the screen.
+
+use crate::ppu::{rgb555_to_rgb888, Ppu};
+
+pub const WIDTH: usize = 256;
+pub const HEIGHT: usize = 224;
+
+// top left corner of the Game Boy screen in the SGB picture
+const SCREEN_X: usize = 48;
+const SCREEN_Y: usize = 40;
+
+// the attribute map has one palette number per 8x8 tile of the screen
+const ATTRIBUTE_WIDTH: usize = 20;
+const ATTRIBUTE_HEIGHT: usize = 18;
+
+const PACKET_SIZE: usize = 16;
+
+// Commands
+const PAL01: u8 = 0x00;
+const PAL23: u8 = 0x01;
+const PAL03: u8 = 0x02;
+const PAL12: u8 = 0x03;
+const ATTR_BLK: u8 = 0x04;
+const ATTR_LIN: u8 = 0x05;
+const ATTR_DIV: u8 = 0x06;
+const ATTR_CHR: u8 = 0x07;
+const MLT_REQ: u8 = 0x11;
+const CHR_TRN: u8 = 0x13;
+const PCT_TRN: u8 = 0x14;
+const MASK_EN: u8 = 0x17;
+
+// palette 1-A, used by the SGB until the game sets its own colors
+const DEFAULT_PALETTE: [u16; 4] = [0x67bf, 0x265b, 0x10b5, 0x2866];
+
+// 0146 - SGB Flag, 03h means the game supports SGB functions
+// 014B - Old Licensee Code, must be 33h for the SGB functions to work
+pub fn is_sgb(header_sgb_flag: u8, old_licensee: u8) -> bool {
+    header_sgb_flag == 0x03 && old_licensee == 0x33
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mask {
+    Cancel,
+    Freeze,
+    Black,
+    Color0,
+}
+
+// VRAM transfer requested by a command, done at the next VBlank
+#[derive(Clone, Copy)]
+enum Transfer {
+    // border tiles 0x00-0x7F or 0x80-0xFF
+    Chr(usize),
+    // border tile map and palettes
+    Pct,
+}
+
+pub struct Sgb {
+    // packet being received from P1
+    receiving: bool,
+    bits: usize,
+    packet: [u8; PACKET_SIZE],
+    // all packets of the current command
+    command: Vec<u8>,
+    previous_p1: u8,
+
+    // MLT_REQ
+    players: u8,
+    player: u8,
+
+    // 4 palettes of 4 RGB555 colors, color 0 is shared
+    palettes: [[u16; 4]; 4],
+    attributes: [u8; ATTRIBUTE_WIDTH * ATTRIBUTE_HEIGHT],
+    mask: Mask,
+    transfer: Option<Transfer>,
+
+    // border: 256 tiles of 32 bytes (SNES 4bpp), 32x28 tile map, palettes 4-7 of 16 colors
+    border_tiles: Vec<u8>,
+    border_map: [u16; 32 * 28],
+    border_palettes: [[u16; 16]; 4],
+
+    pub buffer: Vec<u32>,
+}
+
+impl Default for Sgb {
+    fn default() -> Self {
+        Sgb {
+            receiving: false,
+            bits: 0,
+            packet: [0; PACKET_SIZE],
+            command: vec![],
+            previous_p1: 0x30,
+            players: 1,
+            player: 0,
+            palettes: [DEFAULT_PALETTE; 4],
+            attributes: [0; ATTRIBUTE_WIDTH * ATTRIBUTE_HEIGHT],
+            mask: Mask::Cancel,
+            transfer: None,
+            border_tiles: vec![0; 256 * 32],
+            border_map: [0; 32 * 28],
+            border_palettes: [[0; 16]; 4],
+            buffer: vec![rgb555_to_rgb888(DEFAULT_PALETTE[0]); WIDTH * HEIGHT],
+        }
+    }
+}
+
+impl Sgb {
+    /// Bits 4-5 written to P1.
+    pub fn write_p1(&mut self, value: u8) {
+        let value = value & 0x30;
+        let previous = self.previous_p1;
+        self.previous_p1 = value;
+
+        match value {
+            // reset pulse, starts a packet
+            0x00 => {
+                self.receiving = true;
+                self.bits = 0;
+                self.packet = [0; PACKET_SIZE];
+            }
+            // P14 low: 0, P15 low: 1
+            0x10 | 0x20 if self.receiving && previous == 0x30 => {
+                let bit = (value == 0x10) as u8;
+                self.receive_bit(bit);
+            }
+            // the next joypad is selected when P15 goes back high
+            0x30 if !self.receiving && previous == 0x10 && self.players > 1 => {
+                self.player = (self.player + 1) % self.players;
+            }
+            _ => {}
+        }
+    }
+
+    /// Bits 0-3 of P1 when no buttons are selected: 0x0F minus the joypad number.
+    pub fn joypad_id(&self) -> u8 {
+        0x0f - self.player
+    }
+
+    fn receive_bit(&mut self, bit: u8) {
+        // 128 data bits and a stop bit (0)
+        if self.bits == PACKET_SIZE * 8 {
+            self.receiving = false;
+            if bit == 0 {
+                self.receive_packet();
+            }
+            return;
+        }
+
+        self.packet[self.bits / 8] |= bit << (self.bits % 8);
+        self.bits += 1;
+    }
+
+    fn receive_packet(&mut self) {
+        self.command.extend_from_slice(&self.packet);
+
+        // Byte 0: Command * 8 + Length (number of packets, 1-7)
+        let length = match (self.command[0] & 0x07) as usize {
+            0 => 1,
+            length => length,
+        };
+        if self.command.len() >= length * PACKET_SIZE {
+            let command = std::mem::take(&mut self.command);
+            self.execute(&command);
+        }
+    }
+
+    fn execute(&mut self, data: &[u8]) {
+        let word = |i: usize| (data[i] as u16) | ((data[i + 1] as u16) << 8);
+
+        match data[0] >> 3 {
+            PAL01 | PAL23 | PAL03 | PAL12 => {
+                let (a, b) = match data[0] >> 3 {
+                    PAL01 => (0, 1),
+                    PAL23 => (2, 3),
+                    PAL03 => (0, 3),
+                    _ => (1, 2),
+                };
+                // color 0 is shared by all palettes
+                for palette in self.palettes.iter_mut() {
+                    palette[0] = word(1);
+                }
+                for i in 1..4 {
+                    self.palettes[a][i] = word(1 + i * 2);
+                    self.palettes[b][i] = word(7 + i * 2);
+                }
+            }
+            ATTR_BLK => {
+                for set in 0..(data[1] as usize).min(18) {
+                    let base = 2 + set * 6;
+                    if base + 6 > data.len() {
+                        break;
+                    }
+                    self.attribute_block(&data[base..base + 6]);
+                }
+            }
+            ATTR_LIN => {
+                // Bit 0-4: Line number, Bit 5-6: Palette, Bit 7: 0=Vertical line, 1=Horizontal line
+                for &line in data.iter().skip(2).take((data[1] as usize).min(110)) {
+                    let number = (line & 0x1f) as usize;
+                    let palette = (line >> 5) & 0x03;
+                    match line & 0x80 {
+                        0 => self.fill_attributes(number, number, 0, ATTRIBUTE_HEIGHT - 1, palette),
+                        _ => self.fill_attributes(0, ATTRIBUTE_WIDTH - 1, number, number, palette),
+                    }
+                }
+            }
+            ATTR_DIV => {
+                // Byte 1
+                // Bit 0-1: Palette right/below the line, Bit 2-3: left/above, Bit 4-5: on the line
+                // Bit 6: 0=Vertical line, 1=Horizontal line
+                let after = data[1] & 0x03;
+                let before = (data[1] >> 2) & 0x03;
+                let on = (data[1] >> 4) & 0x03;
+                let position = data[2] as usize;
+
+                for y in 0..ATTRIBUTE_HEIGHT {
+                    for x in 0..ATTRIBUTE_WIDTH {
+                        let coordinate = match data[1] & 0x40 {
+                            0 => x,
+                            _ => y,
+                        };
+                        self.attributes[y * ATTRIBUTE_WIDTH + x] = match coordinate {
+                            c if c < position => before,
+                            c if c == position => on,
+                            _ => after,
+                        };
+                    }
+                }
+            }
+            ATTR_CHR => {
+                // Byte 1-2: X, Y of the first tile, Byte 3-4: number of tiles
+                // Byte 5: 0=Left to right, 1=Top to bottom, Byte 6-: 2 bits per tile, MSB first
+                let mut x = data[1] as usize;
+                let mut y = data[2] as usize;
+                let count = (word(3) as usize).min(ATTRIBUTE_WIDTH * ATTRIBUTE_HEIGHT);
+
+                for i in 0..count {
+                    let byte = match data.get(6 + i / 4) {
+                        Some(byte) => *byte,
+                        None => break,
+                    };
+                    if x >= ATTRIBUTE_WIDTH || y >= ATTRIBUTE_HEIGHT {
+                        break;
+                    }
+                    self.attributes[y * ATTRIBUTE_WIDTH + x] = (byte >> (6 - (i % 4) * 2)) & 0x03;
+
+                    if data[5] == 0 {
+                        x += 1;
+                        if x == ATTRIBUTE_WIDTH {
+                            x = 0;
+                            y += 1;
+                        }
+                    } else {
+                        y += 1;
+                        if y == ATTRIBUTE_HEIGHT {
+                            y = 0;
+                            x += 1;
+                        }
+                    }
+                }
+            }
+            MLT_REQ => {
+                // 0: 1 player, 1: 2 players, 3: 4 players
+                self.players = match data[1] & 0x03 {
+                    1 => 2,
+                    3 => 4,
+                    _ => 1,
+                };
+                self.player = 0;
+            }
+            CHR_TRN => self.transfer = Some(Transfer::Chr((data[1] & 0x01) as usize)),
+            PCT_TRN => self.transfer = Some(Transfer::Pct),
+            MASK_EN => {
+                self.mask = match data[1] & 0x03 {
+                    1 => Mask::Freeze,
+                    2 => Mask::Black,
+                    3 => Mask::Color0,
+                    _ => Mask::Cancel,
+                };
+            }
+            _ => {}
+        }
+    }
+
+    // Byte 0: Control (Bit 0: inside, Bit 1: on the surrounding line, Bit 2: outside)
+    // Byte 1: Palettes (Bit 0-1: inside, Bit 2-3: line, Bit 4-5: outside)
+    // Byte 2-5: X1, Y1, X2, Y2 of the area
+    fn attribute_block(&mut self, block: &[u8]) {
+        let control = block[0] & 0x07;
+        let inside = block[1] & 0x03;
+        let outside = (block[1] >> 4) & 0x03;
+        // the line gets the palette of the only area changed
+        let line = match control {
+            0x01 => inside,
+            0x04 => outside,
+            _ => (block[1] >> 2) & 0x03,
+        };
+        let control = match control {
+            0x01 | 0x04 => control | 0x02,
+            _ => control,
+        };
+        let (x1, y1, x2, y2) = (
+            block[2] as usize,
+            block[3] as usize,
+            block[4] as usize,
+            block[5] as usize,
+        );
+
+        for y in 0..ATTRIBUTE_HEIGHT {
+            for x in 0..ATTRIBUTE_WIDTH {
+                let palette = if x > x1 && x < x2 && y > y1 && y < y2 {
+                    (control & 0x01 > 0, inside)
+                } else if x >= x1 && x <= x2 && y >= y1 && y <= y2 {
+                    (control & 0x02 > 0, line)
+                } else {
+                    (control & 0x04 > 0, outside)
+                };
+
+                if let (true, palette) = palette {
+                    self.attributes[y * ATTRIBUTE_WIDTH + x] = palette;
+                }
+            }
+        }
+    }
+
+    fn fill_attributes(&mut self, x1: usize, x2: usize, y1: usize, y2: usize, palette: u8) {
+        for y in y1..=y2.min(ATTRIBUTE_HEIGHT - 1) {
+            for x in x1..=x2.min(ATTRIBUTE_WIDTH - 1) {
+                self.attributes[y * ATTRIBUTE_WIDTH + x] = palette;
+            }
+        }
+    }
+
+    /// Called at VBlank: runs pending VRAM transfers and draws the frame into `buffer`.
+    pub fn vblank(&mut self, ppu: &Ppu) {
+        if let Some(transfer) = self.transfer.take() {
+            let data = ppu.vram_transfer();
+            match transfer {
+                Transfer::Chr(half) => {
+                    self.border_tiles[half * 0x1000..(half + 1) * 0x1000].copy_from_slice(&data)
+                }
+                Transfer::Pct => {
+                    for (i, entry) in self.border_map.iter_mut().enumerate() {
+                        *entry = (data[i * 2] as u16) | ((data[i * 2 + 1] as u16) << 8);
+                    }
+                    for (i, color) in self.border_palettes.iter_mut().flatten().enumerate() {
+                        let offset = 0x800 + i * 2;
+                        *color = (data[offset] as u16) | ((data[offset + 1] as u16) << 8);
+                    }
+                }
+            }
+        }
+
+        self.render_border();
+
+        if self.mask != Mask::Freeze {
+            self.render_screen(&ppu.shades);
+        }
+    }
+
+    // Tile map entry
+    // Bit 0-7   Tile number
+    // Bit 10-12 Palette number (4-7)
+    // Bit 14    X flip
+    // Bit 15    Y flip
+    fn render_border(&mut self) {
+        let backdrop = rgb555_to_rgb888(self.palettes[0][0]);
+
+        for (i, entry) in self.border_map.iter().enumerate() {
+            let tile = (entry & 0xff) as usize * 32;
+            let palette = ((entry >> 10) & 0x07).saturating_sub(4) as usize;
+
+            for offset_y in 0..8 {
+                let row = match entry & 0x8000 {
+                    0 => offset_y,
+                    _ => 7 - offset_y,
+                };
+                // bit planes 0-1 are in the first 16 bytes, 2-3 in the next 16
+                let planes = [
+                    self.border_tiles[tile + row * 2],
+                    self.border_tiles[tile + row * 2 + 1],
+                    self.border_tiles[tile + 16 + row * 2],
+                    self.border_tiles[tile + 16 + row * 2 + 1],
+                ];
+
+                for offset_x in 0..8 {
+                    let bit = match entry & 0x4000 {
+                        0 => 7 - offset_x,
+                        _ => offset_x,
+                    };
+                    let color = planes.iter().enumerate().fold(0, |color, (plane, v)| {
+                        color | (((v >> bit) & 0x01) << plane)
+                    });
+
+                    let x = (i % 32) * 8 + offset_x;
+                    let y = (i / 32) * 8 + offset_y;
+                    // color 0 is transparent
+                    self.buffer[y * WIDTH + x] = match color {
+                        0 => backdrop,
+                        _ => rgb555_to_rgb888(self.border_palettes[palette][color as usize]),
+                    };
+                }
+            }
+        }
+    }
+
+    fn render_screen(&mut self, shades: &[u8]) {
+        use crate::ppu::{HEIGHT as SCREEN_HEIGHT, WIDTH as SCREEN_WIDTH};
+
+        for y in 0..SCREEN_HEIGHT {
+            for x in 0..SCREEN_WIDTH {
+                let attribute = (y / 8) * ATTRIBUTE_WIDTH + x / 8;
+                let palette = &self.palettes[self.attributes[attribute] as usize];
+                let color = match self.mask {
+                    Mask::Black => 0x0000,
+                    Mask::Color0 => self.palettes[0][0],
+                    _ => palette[shades[y * SCREEN_WIDTH + x] as usize],
+                };
+
+                self.buffer[(SCREEN_Y + y) * WIDTH + SCREEN_X + x] = rgb555_to_rgb888(color);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Sgb;
+
+    fn send_packet(sgb: &mut Sgb, packet: &[u8; 16]) {
+        sgb.write_p1(0x00);
+        sgb.write_p1(0x30);
+        for byte in packet.iter() {
+            for bit in 0..8 {
+                match (byte >> bit) & 0x01 {
+                    0 => sgb.write_p1(0x20),
+                    _ => sgb.write_p1(0x10),
+                }
+                sgb.write_p1(0x30);
+            }
+        }
+        // stop bit
+        sgb.write_p1(0x20);
+        sgb.write_p1(0x30);
+    }
+
+    #[test]
+    fn test_pal01() {
+        let mut sgb = Sgb::default();
+        let mut packet = [0; 16];
+        packet[0] = 0x01;
+        packet[1] = 0xff;
+        packet[2] = 0x7f;
+        packet[3] = 0x1f;
+        send_packet(&mut sgb, &packet);
+
+        assert_eq!(sgb.palettes[0][0], 0x7fff);
+        assert_eq!(sgb.palettes[3][0], 0x7fff);
+        assert_eq!(sgb.palettes[0][1], 0x001f);
+    }
+
+    #[test]
+    fn test_attr_blk() {
+        let mut sgb = Sgb::default();
+        let mut packet = [0; 16];
+        // 1 data set: inside only, palette 2, (1, 1) - (3, 3)
+        packet[..8].copy_from_slice(&[0x21, 0x01, 0x01, 0x02, 0x01, 0x01, 0x03, 0x03]);
+        send_packet(&mut sgb, &packet);
+
+        assert_eq!(sgb.attributes[0], 0);
+        // the surrounding line gets the inside palette
+        assert_eq!(sgb.attributes[20 + 1], 2);
+        assert_eq!(sgb.attributes[2 * 20 + 2], 2);
+        assert_eq!(sgb.attributes[4 * 20 + 4], 0);
+    }
+
+    #[test]
+    fn test_mlt_req() {
+        let mut sgb = Sgb::default();
+        let mut packet = [0; 16];
+        packet[0] = 0x89;
+        packet[1] = 0x01;
+        send_packet(&mut sgb, &packet);
+
+        assert_eq!(sgb.joypad_id(), 0x0f);
+        sgb.write_p1(0x10);
+        sgb.write_p1(0x30);
+        assert_eq!(sgb.joypad_id(), 0x0e);
+        sgb.write_p1(0x10);
+        sgb.write_p1(0x30);
+        assert_eq!(sgb.joypad_id(), 0x0f);
+    }
+}
@@ -0,0 +1,306 @@
+// Frontend drawing in the terminal with crossterm.
+//
+// Each character cell shows two pixels: "▀" (upper half block) in the color of the top
+// pixel over a background in the color of the bottom pixel, so the 160x144 screen takes
+// 160x72 cells. Only the cells that changed since the last frame are written.
+
+use std::io::{self, BufWriter, Stdout, Write};
+use std::time::Duration;
+
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{
+    self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor};
+use crossterm::terminal::{
+    self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen, SetTitle,
+};
+use crossterm::{execute, queue};
+
+use crate::emulator::{Emulator, Frontend};
+use crate::joypad::Button;
+use crate::pacing::Speed;
+
+// Without key release events (terminals without the kitty keyboard protocol), a button is
+// released after this many frames without a key press or repeat.
+const HOLD_FRAMES: u8 = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColorMode {
+    /// 24-bit colors
+    TrueColor,
+    /// the 256 color palette
+    Ansi256,
+}
+
+impl ColorMode {
+    /// 24-bit colors when the terminal announces them in COLORTERM.
+    pub fn detect() -> Self {
+        match std::env::var("COLORTERM") {
+            Ok(value) if value == "truecolor" || value == "24bit" => ColorMode::TrueColor,
+            _ => ColorMode::Ansi256,
+        }
+    }
+
+    fn color(self, pixel: u32) -> Color {
+        let (r, g, b) = ((pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8);
+        match self {
+            ColorMode::TrueColor => Color::Rgb { r, g, b },
+            ColorMode::Ansi256 => Color::AnsiValue(ansi256(r, g, b)),
+        }
+    }
+}
+
+// Levels of the 6x6x6 color cube (16-231)
+// ref. https://en.wikipedia.org/wiki/ANSI_escape_code#8-bit
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+// The nearest color of the cube or of the gray ramp (232-255: 8, 18, ..., 238).
+fn ansi256(r: u8, g: u8, b: u8) -> u8 {
+    let nearest_level = |v: u8| {
+        (0..6)
+            .min_by_key(|&i| (CUBE_LEVELS[i] as i32 - v as i32).abs())
+            .unwrap()
+    };
+    let distance = |(r2, g2, b2): (u8, u8, u8)| {
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        d(r, r2) + d(g, g2) + d(b, b2)
+    };
+
+    let (ri, gi, bi) = (nearest_level(r), nearest_level(g), nearest_level(b));
+    let cube = (CUBE_LEVELS[ri], CUBE_LEVELS[gi], CUBE_LEVELS[bi]);
+
+    let average = (r as u32 + g as u32 + b as u32) / 3;
+    let gray_index = (average.saturating_sub(3) / 10).min(23) as u8;
+    let gray_level = 8 + gray_index * 10;
+
+    if distance((gray_level, gray_level, gray_level)) < distance(cube) {
+        232 + gray_index
+    } else {
+        16 + 36 * ri as u8 + 6 * gi as u8 + bi as u8
+    }
+}
+
+// Keyboard layout
+// Hotkeys: Tab toggles fast-forward, ` toggles slow-motion, Space pauses, N runs a frame
+// while paused, P switches the palette, F1-F9 toggle the cheats, F12 takes a screenshot,
+// Escape or Ctrl-C quits.
+fn button(code: KeyCode) -> Option<Button> {
+    match code {
+        KeyCode::Right => Some(Button::Right),
+        KeyCode::Left => Some(Button::Left),
+        KeyCode::Up => Some(Button::Up),
+        KeyCode::Down => Some(Button::Down),
+        KeyCode::Char('x') => Some(Button::A),
+        KeyCode::Char('z') => Some(Button::B),
+        KeyCode::Backspace => Some(Button::Select),
+        KeyCode::Enter => Some(Button::Start),
+        _ => None,
+    }
+}
+
+pub struct TerminalFrontend {
+    out: BufWriter<Stdout>,
+    colors: ColorMode,
+    // (top, bottom) pixels of the cells on screen, None when unknown
+    cells: Vec<Option<(u32, u32)>>,
+    // the terminal size in cells
+    columns: usize,
+    rows: usize,
+    // the terminal reports key releases
+    release_events: bool,
+    // frames left before each button of `Button::ALL` is released, 0 = released
+    held: [u8; 8],
+    screenshot_scale: usize,
+}
+
+impl TerminalFrontend {
+    /// Switches the terminal to raw mode and the alternate screen until dropped.
+    pub fn new(colors: ColorMode, screenshot_scale: usize) -> io::Result<Self> {
+        let (columns, rows) = terminal::size()?;
+
+        terminal::enable_raw_mode()?;
+        let mut out = BufWriter::new(io::stdout());
+        execute!(out, EnterAlternateScreen, Hide, Clear(ClearType::All))?;
+
+        // key release events (kitty keyboard protocol). The releases of the keys producing
+        // text (X, Z, Enter...) are only reported with all the keys as escape codes.
+        // ref. https://sw.kovidgoyal.net/kitty/keyboard-protocol/#report-events
+        let release_events = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if release_events {
+            execute!(
+                out,
+                PushKeyboardEnhancementFlags(
+                    KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES
+                        | KeyboardEnhancementFlags::REPORT_EVENT_TYPES
+                        | KeyboardEnhancementFlags::REPORT_ALL_KEYS_AS_ESCAPE_CODES
+                )
+            )?;
+        }
+
+        Ok(TerminalFrontend {
+            out,
+            colors,
+            cells: vec![],
+            columns: columns as usize,
+            rows: rows as usize,
+            release_events,
+            held: [0; 8],
+            screenshot_scale,
+        })
+    }
+
+    fn draw(&mut self, buffer: &[u32], width: usize, height: usize) -> io::Result<()> {
+        let rows = height.div_ceil(2);
+        if self.cells.len() != width * rows {
+            self.cells = vec![None; width * rows];
+        }
+
+        // the cursor position and colors after the last cell written
+        let mut cursor = None;
+        let mut colors = None;
+
+        // the screen is cut to the terminal size
+        for row in 0..rows.min(self.rows) {
+            for x in 0..width.min(self.columns) {
+                let top = buffer[row * 2 * width + x];
+                let bottom = match row * 2 + 1 < height {
+                    true => buffer[(row * 2 + 1) * width + x],
+                    false => 0xFF000000,
+                };
+
+                let cell = &mut self.cells[row * width + x];
+                if *cell == Some((top, bottom)) {
+                    continue;
+                }
+                *cell = Some((top, bottom));
+
+                if cursor != Some((x, row)) {
+                    queue!(self.out, MoveTo(x as u16, row as u16))?;
+                }
+                if colors != Some((top, bottom)) {
+                    queue!(
+                        self.out,
+                        SetForegroundColor(self.colors.color(top)),
+                        SetBackgroundColor(self.colors.color(bottom))
+                    )?;
+                    colors = Some((top, bottom));
+                }
+                queue!(self.out, Print('▀'))?;
+                cursor = Some((x + 1, row));
+            }
+        }
+
+        queue!(self.out, ResetColor)?;
+        self.out.flush()
+    }
+
+    fn key(&mut self, key: KeyEvent, emulator: &mut Emulator) -> bool {
+        let pressed = key.kind != KeyEventKind::Release;
+
+        match key.code {
+            KeyCode::Esc => return false,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return false,
+            KeyCode::F(12) if key.kind == KeyEventKind::Press => {
+                let title = match emulator.save_screenshot(self.screenshot_scale) {
+                    Ok(path) => format!("saved {}", path.display()),
+                    Err(e) => format!("failed to save the screenshot: {}", e),
+                };
+                // printing would scroll the picture
+                let _ = execute!(self.out, SetTitle(title));
+            }
+            KeyCode::Char('p') if key.kind == KeyEventKind::Press => emulator.next_palette(),
+            KeyCode::F(n @ 1..=9) if key.kind == KeyEventKind::Press => {
+                if let Some(state) = emulator.toggle_cheat(n as usize - 1) {
+                    let _ = execute!(self.out, SetTitle(state));
+                }
+            }
+            KeyCode::Tab if key.kind == KeyEventKind::Press => {
+                emulator.pacer.toggle_speed(Speed::FastForward)
+            }
+            KeyCode::Char('`') if key.kind == KeyEventKind::Press => {
+                emulator.pacer.toggle_speed(Speed::SlowMotion)
+            }
+            KeyCode::Char(' ') if key.kind == KeyEventKind::Press => emulator.pacer.toggle_pause(),
+            KeyCode::Char('n') if key.kind != KeyEventKind::Release => {
+                emulator.pacer.advance_frame()
+            }
+            code => {
+                if let Some(button) = button(code) {
+                    if !self.release_events {
+                        let i = Button::ALL.iter().position(|b| *b == button).unwrap();
+                        self.held[i] = HOLD_FRAMES;
+                    }
+                    emulator.set_button(button, pressed);
+                }
+            }
+        }
+
+        true
+    }
+}
+
+impl Frontend for TerminalFrontend {
+    fn present(&mut self, buffer: &[u32], width: usize, height: usize) {
+        self.draw(buffer, width, height).unwrap();
+    }
+
+    fn poll_input(&mut self, emulator: &mut Emulator) -> bool {
+        if !self.release_events {
+            for (i, held) in self.held.iter_mut().enumerate() {
+                if *held == 1 {
+                    emulator.set_button(Button::ALL[i], false);
+                }
+                *held = held.saturating_sub(1);
+            }
+        }
+
+        while event::poll(Duration::ZERO).unwrap_or(false) {
+            match event::read() {
+                Ok(Event::Key(key)) => {
+                    if !self.key(key, emulator) {
+                        return false;
+                    }
+                }
+                Ok(Event::Resize(columns, rows)) => {
+                    self.columns = columns as usize;
+                    self.rows = rows as usize;
+                    // redraw everything
+                    self.cells.clear();
+                    let _ = execute!(self.out, Clear(ClearType::All));
+                }
+                Ok(_) => {}
+                Err(_) => return false,
+            }
+        }
+
+        true
+    }
+}
+
+impl Drop for TerminalFrontend {
+    fn drop(&mut self) {
+        if self.release_events {
+            let _ = execute!(self.out, PopKeyboardEnhancementFlags);
+        }
+        let _ = execute!(self.out, ResetColor, Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ansi256;
+
+    #[test]
+    fn test_ansi256() {
+        assert_eq!(ansi256(0, 0, 0), 16);
+        assert_eq!(ansi256(255, 255, 255), 231);
+        assert_eq!(ansi256(255, 0, 0), 196);
+        // gray ramp
+        assert_eq!(ansi256(128, 128, 128), 244);
+        // DMG green
+        assert_eq!(ansi256(0x9B, 0xBC, 0x0F), 106);
+    }
+}
@@ -35,7 +35,7 @@ impl Timer {
             }
             0xff05 => self.tima = val,
             0xff06 => self.tma = val,
-            0xff07 => self.tac = val,
+            0xff07 => self.tac = val & 0x07,
             _ => panic!("unexpected address #{:X}", address),
         }
     }
@@ -46,7 +46,8 @@ impl Timer {
             0xff04 => (self.div >> 8) as u8,
             0xff05 => self.tima,
             0xff06 => self.tma,
-            0xff07 => self.tac,
+            // upper 5 bits are unused and read as 1
+            0xff07 => 0xf8 | self.tac,
             _ => panic!("unexpected address #{:X}", address),
         }
     }
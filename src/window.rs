@@ -0,0 +1,120 @@
+// Frontend drawing in a window with minifb.
+
+use minifb::{Key, KeyRepeat, Window, WindowOptions};
+
+use crate::emulator::{Emulator, Frontend};
+use crate::joypad::Button;
+use crate::pacing::Speed;
+
+// Keyboard layout
+// Hotkeys: hold Tab to fast-forward, hold ` for slow-motion, Space pauses, N runs a frame
+// while paused, P switches the palette, F1-F9 toggle the cheats, F12 takes a screenshot
+// and Escape quits. I, J, K and L tilt MBC7 cartridges.
+const KEYS: [(Key, Button); 8] = [
+    (Key::Right, Button::Right),
+    (Key::Left, Button::Left),
+    (Key::Up, Button::Up),
+    (Key::Down, Button::Down),
+    (Key::X, Button::A),
+    (Key::Z, Button::B),
+    (Key::Backspace, Button::Select),
+    (Key::Enter, Button::Start),
+];
+
+const CHEAT_KEYS: [Key; 9] = [
+    Key::F1,
+    Key::F2,
+    Key::F3,
+    Key::F4,
+    Key::F5,
+    Key::F6,
+    Key::F7,
+    Key::F8,
+    Key::F9,
+];
+
+pub struct WindowFrontend {
+    window: Window,
+    screenshot_scale: usize,
+}
+
+impl WindowFrontend {
+    /// Opens a window showing `width` x `height` pixels enlarged by `scale`.
+    pub fn new(width: usize, height: usize, scale: usize, screenshot_scale: usize) -> Self {
+        let window = Window::new(
+            "GameBoy Emulator",
+            width * scale,
+            height * scale,
+            WindowOptions::default(),
+        )
+        .unwrap_or_else(|e| {
+            panic!("{}", e);
+        });
+
+        WindowFrontend {
+            window,
+            screenshot_scale,
+        }
+    }
+}
+
+impl Frontend for WindowFrontend {
+    fn present(&mut self, buffer: &[u32], width: usize, height: usize) {
+        self.window
+            .update_with_buffer(buffer, width, height)
+            .unwrap();
+    }
+
+    fn poll_input(&mut self, emulator: &mut Emulator) -> bool {
+        if !self.window.is_open() || self.window.is_key_down(Key::Escape) {
+            return false;
+        }
+
+        for (key, button) in KEYS.iter() {
+            emulator.set_button(*button, self.window.is_key_down(*key));
+        }
+
+        let axis = |negative: Key, positive: Key| {
+            self.window.is_key_down(positive) as i32 as f32
+                - self.window.is_key_down(negative) as i32 as f32
+        };
+        emulator.set_tilt(axis(Key::J, Key::L), axis(Key::I, Key::K));
+
+        if self.window.is_key_pressed(Key::F12, KeyRepeat::No) {
+            match emulator.save_screenshot(self.screenshot_scale) {
+                Ok(path) => println!("saved {}", path.display()),
+                Err(e) => eprintln!("failed to save the screenshot: {}", e),
+            }
+        }
+
+        if self.window.is_key_pressed(Key::P, KeyRepeat::No) {
+            emulator.next_palette();
+        }
+
+        for (i, key) in CHEAT_KEYS.iter().enumerate() {
+            if self.window.is_key_pressed(*key, KeyRepeat::No) {
+                if let Some(state) = emulator.toggle_cheat(i) {
+                    println!("{}", state);
+                }
+            }
+        }
+
+        let speed = if self.window.is_key_down(Key::Tab) {
+            Speed::FastForward
+        } else if self.window.is_key_down(Key::Backquote) {
+            Speed::SlowMotion
+        } else {
+            Speed::Normal
+        };
+        emulator.pacer.set_speed(speed);
+
+        if self.window.is_key_pressed(Key::Space, KeyRepeat::No) {
+            emulator.pacer.toggle_pause();
+        }
+        if self.window.is_key_pressed(Key::N, KeyRepeat::Yes) {
+            emulator.pacer.advance_frame();
+        }
+
+        true
+    }
+}
@@ -0,0 +1,290 @@
+// Synthetic test ROMs built by the tests, and the reference pictures computed from what they
+// draw rather than captured from the emulator.
+//
+// fibonacci.gb:  computes 3/5/8/13/21/34 and reports them the mooneye way
+// joypad.gb:     mixes the joypad state of every frame into its registers
+// mbc1_banks.gb: checks the MBC1 ROM banking, reported the mooneye way
+// shades.gb:     draws stripes of the 4 shades and a sprite, then LD B,B like dmg-acid2
+//
+// Each test uses some of them.
+#![allow(dead_code)]
+
+use gbrust::catridge::header::{global_checksum, header_checksum, LOGO};
+use gbrust::ppu::{DARKEST_GREEN, DARK_GREEN, HEIGHT, LIGHTEST_GREEN, LIGHT_GREEN, WIDTH};
+
+const CODE: usize = 0x0150;
+// hl: source, de: destination, bc: length
+const MEMCPY: usize = 0x0200;
+
+const ROM_BANK_SIZE: usize = 0x4000;
+
+// `banks` of 16KB: `code` at $0150, `data` at the given addresses
+fn rom(
+    title: &str,
+    cartridge_type: u8,
+    banks: usize,
+    code: &[u8],
+    data: &[(usize, &[u8])],
+) -> Vec<u8> {
+    let mut rom = vec![0; banks * ROM_BANK_SIZE];
+    // nop; jp $0150
+    rom[0x0100..0x0104].copy_from_slice(&[0x00, 0xc3, 0x50, 0x01]);
+    rom[0x0104..0x0134].copy_from_slice(&LOGO);
+    rom[0x0134..0x0134 + title.len()].copy_from_slice(title.as_bytes());
+    rom[0x0147] = cartridge_type;
+    // 32KB << n
+    rom[0x0148] = (banks / 2).trailing_zeros() as u8;
+    rom[CODE..CODE + code.len()].copy_from_slice(code);
+    rom[MEMCPY..MEMCPY + 9].copy_from_slice(&[
+        0x2a, // ld a,(hl+)
+        0x12, // ld (de),a
+        0x13, // inc de
+        0x0b, // dec bc
+        0x78, // ld a,b
+        0xb1, // or c
+        0x20, 0xf8, // jr nz,$0200
+        0xc9, // ret
+    ]);
+    for (address, bytes) in data.iter() {
+        rom[*address..*address + bytes.len()].copy_from_slice(bytes);
+    }
+    rom
+}
+
+fn checksums(mut rom: Vec<u8>) -> Vec<u8> {
+    rom[0x014d] = header_checksum(&rom);
+    let checksum = global_checksum(&rom);
+    rom[0x014e..0x0150].copy_from_slice(&checksum.to_be_bytes());
+    rom
+}
+
+pub fn fibonacci() -> Vec<u8> {
+    #[rustfmt::skip]
+    let code = [
+        0x31, 0xfe, 0xff, // ld sp,$fffe
+        0x21, 0x00, 0xc0, // ld hl,$c000
+        0x16, 0x01,       // ld d,1
+        0x1e, 0x02,       // ld e,2
+        0x0e, 0x06,       // ld c,6
+        // $015C: the next number to $C000-$C005
+        0x7a,             // ld a,d
+        0x83,             // add a,e
+        0x53,             // ld d,e
+        0x5f,             // ld e,a
+        0x22,             // ld (hl+),a
+        0x0d,             // dec c
+        0x20, 0xf8,       // jr nz,$015C
+        // then to the registers
+        0x21, 0x00, 0xc0, // ld hl,$c000
+        0x2a, 0x47,       // ld a,(hl+); ld b,a
+        0x2a, 0x4f,       // ld a,(hl+); ld c,a
+        0x2a, 0x57,       // ld a,(hl+); ld d,a
+        0x2a, 0x5f,       // ld a,(hl+); ld e,a
+        0x2a,             // ld a,(hl+)
+        0x6e,             // ld l,(hl)
+        0x67,             // ld h,a
+        0x40,             // ld b,b
+        0x18, 0xfe,       // jr -2
+    ];
+    checksums(rom("FIBONACCI", 0x00, 2, &code, &[]))
+}
+
+pub fn joypad() -> Vec<u8> {
+    #[rustfmt::skip]
+    let code = [
+        0x31, 0xfe, 0xff, // ld sp,$fffe
+        0x0e, 0x00,       // ld c,0: the states mixed
+        0x16, 0x00,       // ld d,0: the sum of the states
+        0x1e, 0x00,       // ld e,0: the frames
+        // $0159: once per frame in VBlank
+        0xf0, 0x44,       // ldh a,(LY)
+        0xfe, 0x90,       // cp 144
+        0x20, 0xfa,       // jr nz,$0159
+        0x3e, 0x20,       // ld a,$20: directions
+        0xe0, 0x00,       // ldh (P1),a
+        0xf0, 0x00,       // ldh a,(P1)
+        0xf0, 0x00,       // ldh a,(P1)
+        0xe6, 0x0f,       // and $0f
+        0x47,             // ld b,a
+        0x3e, 0x10,       // ld a,$10: buttons
+        0xe0, 0x00,       // ldh (P1),a
+        0xf0, 0x00,       // ldh a,(P1)
+        0xf0, 0x00,       // ldh a,(P1)
+        0xe6, 0x0f,       // and $0f
+        0xcb, 0x37,       // swap a
+        0xb0,             // or b
+        0x47,             // ld b,a
+        0x82,             // add a,d
+        0x57,             // ld d,a
+        0x79,             // ld a,c
+        0x07,             // rlca
+        0xa8,             // xor b
+        0x4f,             // ld c,a
+        0x1c,             // inc e
+        // $017F: wait for the end of VBlank
+        0xf0, 0x44,       // ldh a,(LY)
+        0xfe, 0x90,       // cp 144
+        0x28, 0xfa,       // jr z,$017F
+        0x18, 0xd2,       // jr $0159
+    ];
+    checksums(rom("JOYPAD", 0x00, 2, &code, &[]))
+}
+
+// the number of each bank is at $2000 in the bank
+const MBC1_BANKS: usize = 64;
+const MBC1_MARKER: usize = 0x2000;
+
+pub fn mbc1_banks() -> Vec<u8> {
+    #[rustfmt::skip]
+    let code = [
+        0x31, 0xfe, 0xff, // ld sp,$fffe
+        0x0e, 0x01,       // ld c,1
+        // $0155: banks 1-31 at $4000-$7FFF
+        0x79,             // ld a,c
+        0xea, 0x00, 0x20, // ld ($2000),a
+        0xfa, 0x00, 0x60, // ld a,($6000)
+        0xb9,             // cp c
+        0x20, 0x3d,       // jr nz,fail
+        0x0c,             // inc c
+        0x79,             // ld a,c
+        0xfe, 0x20,       // cp 32
+        0x20, 0xf0,       // jr nz,$0155
+        // bank 0 selects bank 1
+        0xaf,             // xor a
+        0xea, 0x00, 0x20, // ld ($2000),a
+        0xfa, 0x00, 0x60, // ld a,($6000)
+        0xfe, 0x01,       // cp 1
+        0x20, 0x2c,       // jr nz,fail
+        // BANK2: bank $22
+        0x3e, 0x01,       // ld a,1
+        0xea, 0x00, 0x40, // ld ($4000),a
+        0x3e, 0x02,       // ld a,2
+        0xea, 0x00, 0x20, // ld ($2000),a
+        0xfa, 0x00, 0x60, // ld a,($6000)
+        0xfe, 0x22,       // cp $22
+        0x20, 0x1b,       // jr nz,fail
+        // mode 1: bank $20 at $0000-$3FFF, which has the same code
+        0x3e, 0x01,       // ld a,1
+        0xea, 0x00, 0x60, // ld ($6000),a
+        0xfa, 0x00, 0x20, // ld a,($2000)
+        0xfe, 0x20,       // cp $20
+        0x20, 0x0f,       // jr nz,fail
+        0x06, 3, 0x0e, 5, 0x16, 8, 0x1e, 13, 0x26, 21, 0x2e, 34,
+        0x40,             // ld b,b
+        0x18, 0xfe,       // jr -2
+        // fail
+        0x06, 0x42,       // ld b,$42
+        0x48, 0x50, 0x58, 0x60, 0x68, // ld c,b; ld d,b; ld e,b; ld h,b; ld l,b
+        0x40,             // ld b,b
+        0x18, 0xfe,       // jr -2
+    ];
+    let mut rom = rom("MBC1 BANKS", 0x01, MBC1_BANKS, &code, &[]);
+    let program = rom[..MBC1_MARKER].to_vec();
+    rom[0x20 * ROM_BANK_SIZE..0x20 * ROM_BANK_SIZE + MBC1_MARKER].copy_from_slice(&program);
+    for bank in 0..MBC1_BANKS {
+        rom[bank * ROM_BANK_SIZE + MBC1_MARKER] = bank as u8;
+    }
+    checksums(rom)
+}
+
+// the stripes are 5 tiles wide, the sprite is at (72, 64)
+const STRIPE_TILES: usize = 5;
+const SPRITE: (usize, usize) = (72, 64);
+const BGP: u8 = 0xe4;
+const OBP0: u8 = 0x1b;
+
+pub fn shades() -> Vec<u8> {
+    // tiles 0-3: filled with the color 0-3, tile 4: color 1 inside a transparent border
+    let mut tiles = vec![];
+    for color in 0..4u8 {
+        let low = if color & 0x01 > 0 { 0xff } else { 0x00 };
+        let high = if color & 0x02 > 0 { 0xff } else { 0x00 };
+        for _ in 0..8 {
+            tiles.extend_from_slice(&[low, high]);
+        }
+    }
+    for row in 0..8 {
+        let low = if row == 0 || row == 7 { 0x00 } else { 0x7e };
+        tiles.extend_from_slice(&[low, 0x00]);
+    }
+
+    // 32x18 tiles
+    let mut map = vec![0; 32 * 18];
+    for (i, tile) in map.iter_mut().enumerate() {
+        *tile = ((i % 32) / STRIPE_TILES).min(3) as u8;
+    }
+
+    // Y and X are the same, tile 4, OBP0
+    let mut oam = vec![0; 0xa0];
+    oam[..4].copy_from_slice(&[(SPRITE.1 + 16) as u8, (SPRITE.0 + 8) as u8, 0x04, 0x00]);
+
+    #[rustfmt::skip]
+    let code = [
+        0x31, 0xfe, 0xff, // ld sp,$fffe
+        // $0153: turn the LCD off in VBlank
+        0xf0, 0x44,       // ldh a,(LY)
+        0xfe, 0x90,       // cp 144
+        0x38, 0xfa,       // jr c,$0153
+        0xaf,             // xor a
+        0xe0, 0x40,       // ldh (LCDC),a
+        0x21, 0x00, 0x10, // ld hl,$1000
+        0x11, 0x00, 0x80, // ld de,$8000
+        0x01, 0x50, 0x00, // ld bc,80
+        0xcd, 0x00, 0x02, // call memcpy
+        0x21, 0x00, 0x11, // ld hl,$1100
+        0x11, 0x00, 0x98, // ld de,$9800
+        0x01, 0x40, 0x02, // ld bc,576
+        0xcd, 0x00, 0x02, // call memcpy
+        0x21, 0x00, 0x14, // ld hl,$1400
+        0x11, 0x00, 0xfe, // ld de,$fe00
+        0x01, 0xa0, 0x00, // ld bc,160
+        0xcd, 0x00, 0x02, // call memcpy
+        0x3e, BGP,        // ld a,BGP
+        0xe0, 0x47,       // ldh (BGP),a
+        0x3e, OBP0,       // ld a,OBP0
+        0xe0, 0x48,       // ldh (OBP0),a
+        0xaf,             // xor a
+        0xe0, 0x42,       // ldh (SCY),a
+        0xe0, 0x43,       // ldh (SCX),a
+        0x3e, 0x93,       // ld a,$93: LCD, tiles at $8000, OBJ and BG on
+        0xe0, 0x40,       // ldh (LCDC),a
+        // $0191: wait for the VBlank of a whole frame
+        0xf0, 0x44,       // ldh a,(LY)
+        0xfe, 0x90,       // cp 144
+        0x20, 0xfa,       // jr nz,$0191
+        0xf0, 0x44,       // ldh a,(LY)
+        0xb7,             // or a
+        0x20, 0xfb,       // jr nz,$0197
+        0xf0, 0x44,       // ldh a,(LY)
+        0xfe, 0x90,       // cp 144
+        0x20, 0xfa,       // jr nz,$019C
+        0x40,             // ld b,b
+        0x18, 0xfe,       // jr -2
+    ];
+    checksums(rom(
+        "SHADES",
+        0x00,
+        2,
+        &code,
+        &[(0x1000, &tiles), (0x1100, &map), (0x1400, &oam)],
+    ))
+}
+
+// what shades.gb draws with the default palette
+pub fn shades_picture() -> Vec<u32> {
+    let colors = [LIGHTEST_GREEN, LIGHT_GREEN, DARK_GREEN, DARKEST_GREEN];
+    let shade = |palette: u8, color: usize| (palette >> (color * 2)) as usize & 0x03;
+
+    let mut buffer = vec![0; WIDTH * HEIGHT];
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let (sx, sy) = (x.wrapping_sub(SPRITE.0), y.wrapping_sub(SPRITE.1));
+            let color = match (1..7).contains(&sx) && (1..7).contains(&sy) {
+                true => colors[shade(OBP0, 1)],
+                false => colors[shade(BGP, (x / (8 * STRIPE_TILES)).min(3))],
+            };
+            buffer[x + y * WIDTH] = color;
+        }
+    }
+    buffer
+}
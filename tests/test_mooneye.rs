@@ -0,0 +1,157 @@
+use gbrust::cpu;
+use gbrust::harness::{Condition, Mooneye, TestRom};
+
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::fs;
+use std::panic;
+use std::path::{Path, PathBuf};
+
+// 32KB ROM without MBC running `code` from $0100
+fn write_rom(name: &str, code: &[u8]) -> PathBuf {
+    let mut rom = vec![0; 0x8000];
+    rom[0x100..0x100 + code.len()].copy_from_slice(code);
+
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+    fs::write(&path, rom).unwrap();
+    path
+}
+
+// loads the registers, then LD B,B and JR -2
+fn registers_rom(name: &str, values: [u8; 6]) -> PathBuf {
+    let [b, c, d, e, h, l] = values;
+    write_rom(
+        name,
+        &[
+            0x06, b, 0x0e, c, 0x16, d, 0x1e, e, 0x26, h, 0x2e, l, 0x40, 0x18, 0xfe,
+        ],
+    )
+}
+
+#[test]
+fn test_mooneye_detector() {
+    let pass = registers_rom("mooneye_pass.gb", [3, 5, 8, 13, 21, 34]);
+    let mut cpu = cpu::Cpu::new(&pass.to_string_lossy());
+    let mooneye = Mooneye::attach(&mut cpu);
+    for _ in 0..100 {
+        cpu.step();
+    }
+    assert_eq!(mooneye.result(), Some(Ok(())));
+
+    let fail = registers_rom("mooneye_fail.gb", [0x42; 6]);
+    let mut cpu = cpu::Cpu::new(&fail.to_string_lossy());
+    let mooneye = Mooneye::attach(&mut cpu);
+    assert_eq!(mooneye.result(), None);
+    for _ in 0..100 {
+        cpu.step();
+    }
+    assert!(mooneye.result().unwrap().is_err());
+}
+
+fn find_roms(dir: &Path, roms: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            find_roms(&path, roms);
+        } else if path.extension() == Some(OsStr::new("gb")) {
+            roms.push(path);
+        }
+    }
+}
+
+// Runs a mooneye test ROM, unsupported hardware (e.g. an MBC) panics.
+fn run_mooneye(rom: &Path) -> Result<(), String> {
+    let test = TestRom {
+        rom: rom.to_path_buf(),
+        condition: Condition::Mooneye,
+        frames: 1200,
+        reference: None,
+    };
+    let result = panic::catch_unwind(|| test.run(env!("CARGO_TARGET_TMPDIR")))
+        .unwrap_or_else(|_| Err("panicked".to_string()));
+    println!("{}: {:?}", rom.display(), result);
+    result
+}
+
+// The ROMs aren't in the repository: build https://github.com/Gekkio/mooneye-test-suite and
+// copy its build directory to roms/mooneye, then run `cargo test -- --ignored`.
+const MOONEYE_DIR: &str = "roms/mooneye";
+
+fn mooneye_dir(suite: &str) -> PathBuf {
+    let dir = Path::new(MOONEYE_DIR).join(suite);
+    assert!(
+        dir.is_dir(),
+        "{} not found, copy the mooneye test ROMs to {}",
+        dir.display(),
+        MOONEYE_DIR
+    );
+    dir
+}
+
+// Runs the acceptance tests and prints the pass rate. Tests listed in roms/mooneye/passing.txt
+// (paths relative to roms/mooneye) must keep passing.
+#[test]
+#[ignore = "needs the mooneye test ROMs in roms/mooneye"]
+fn test_mooneye_acceptance() {
+    let dir = mooneye_dir("acceptance");
+
+    let passing: HashSet<PathBuf> = fs::read_to_string(Path::new(MOONEYE_DIR).join("passing.txt"))
+        .unwrap_or_default()
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| Path::new(MOONEYE_DIR).join(line))
+        .collect();
+
+    let mut roms = vec![];
+    find_roms(&dir, &mut roms);
+    roms.sort();
+
+    let mut passed = 0;
+    let mut regressions = vec![];
+    for rom in roms.iter() {
+        match run_mooneye(rom) {
+            Ok(_) => passed += 1,
+            Err(e) if passing.contains(rom) => {
+                regressions.push(format!("{}: {}", rom.display(), e))
+            }
+            Err(_) => {}
+        }
+    }
+
+    println!("passed {}/{}", passed, roms.len());
+    assert!(regressions.is_empty(), "\n{}", regressions.join("\n"));
+}
+
+// The MBC1 tests must all pass.
+#[test]
+#[ignore = "needs the mooneye test ROMs in roms/mooneye"]
+fn test_mooneye_mbc1() {
+    let dir = mooneye_dir("emulator-only/mbc1");
+
+    let failures: Vec<String> = [
+        "bits_bank1",
+        "bits_bank2",
+        "bits_mode",
+        "bits_ramg",
+        "ram_64kb",
+        "ram_256kb",
+        "rom_512kb",
+        "rom_1Mb",
+        "rom_2Mb",
+        "rom_4Mb",
+        "rom_8Mb",
+        "rom_16Mb",
+        "multicart_rom_8Mb",
+    ]
+    .iter()
+    .map(|name| dir.join(name).with_extension("gb"))
+    .filter_map(|rom| {
+        run_mooneye(&rom)
+            .err()
+            .map(|e| format!("{}: {}", rom.display(), e))
+    })
+    .collect();
+
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
@@ -0,0 +1,90 @@
+mod common;
+
+use gbrust::emulator::Emulator;
+use gbrust::joypad::Button;
+use gbrust::movie::{Movie, Recorder};
+
+use std::fs;
+use std::path::Path;
+
+// a ROM whose registers depend on the joypad state of every frame
+fn joypad_rom(name: &str) -> String {
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+    fs::write(&path, common::joypad()).unwrap();
+    path.to_string_lossy().to_string()
+}
+
+// Plays the recorded input and ends in the same state.
+#[test]
+fn test_movie_playback() {
+    let rom = &joypad_rom("joypad.gb");
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join("joypad.gbm");
+
+    let mut emulator = Emulator::new(rom);
+    let movie = Movie::new(emulator.cpu.mmu.rom());
+    emulator.record_movie(Recorder::create(&path, &movie).unwrap());
+    for frame in 0..120 {
+        emulator.set_button(Button::Start, frame % 7 == 0);
+        emulator.set_button(Button::Left, frame % 3 == 0);
+        emulator.run_frame();
+    }
+    let registers = emulator.cpu.registers();
+    let buffer = emulator.cpu.mmu.ppu.buffer.clone();
+
+    // the input changes the state
+    let mut emulator = Emulator::new(rom);
+    for _ in 0..120 {
+        emulator.run_frame();
+    }
+    assert_ne!(emulator.cpu.registers(), registers);
+
+    let movie = Movie::load(&path).unwrap();
+    assert_eq!(movie.frames.len(), 120);
+
+    let mut emulator = Emulator::new(rom);
+    emulator.play_movie(movie).unwrap();
+    for _ in 0..120 {
+        // the frontend's input is ignored
+        emulator.set_button(Button::A, true);
+        emulator.run_frame();
+    }
+    assert!(emulator.is_playing());
+    assert_eq!(emulator.cpu.registers(), registers);
+    assert_eq!(emulator.cpu.mmu.ppu.buffer, buffer);
+
+    emulator.run_frame();
+    assert!(!emulator.is_playing());
+
+    // movies start at power-on
+    let movie = Movie::load(&path).unwrap();
+    assert!(emulator.play_movie(movie).is_err());
+}
+
+// Keeps the first frames of a movie and records new input after them.
+#[test]
+fn test_movie_rerecord() {
+    let rom = &joypad_rom("joypad_rerecord.gb");
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join("joypad_rerecord.gbm");
+
+    let mut emulator = Emulator::new(rom);
+    let mut movie = Movie::new(emulator.cpu.mmu.rom());
+    movie.frames = vec![0x01; 60];
+    movie.save(&path).unwrap();
+
+    movie.frames.truncate(40);
+    let recorder = Recorder::append(&path, movie.frames.len()).unwrap();
+    emulator.rerecord_movie(movie, recorder).unwrap();
+    for _ in 0..40 {
+        emulator.run_frame();
+    }
+    emulator.set_button(Button::Start, true);
+    for _ in 0..10 {
+        emulator.run_frame();
+    }
+    assert!(!emulator.is_playing());
+
+    let frames = Movie::load(&path).unwrap().frames;
+    assert_eq!(frames.len(), 50);
+    assert!(frames[..40].iter().all(|state| *state == 0x01));
+    assert!(frames[40..].iter().all(|state| *state != 0x01));
+}
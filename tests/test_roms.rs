@@ -0,0 +1,56 @@
+mod common;
+
+use gbrust::harness::{self, Condition, TestRom};
+use gbrust::image;
+use gbrust::ppu::{HEIGHT, WIDTH};
+
+use std::fs;
+use std::path::Path;
+
+fn run(tests: &[TestRom], output_dir: &Path) {
+    let mut failures = vec![];
+    for t in tests.iter() {
+        println!("{}", t.rom.display());
+        if let Err(e) = t.run(output_dir) {
+            failures.push(format!("{}: {}", t.rom.display(), e));
+        }
+    }
+
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+#[test]
+fn test_roms() {
+    let tests = harness::load_manifest("roms/manifest.txt").unwrap();
+    let output_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("test_roms");
+    run(&tests, &output_dir);
+}
+
+#[test]
+fn test_generated_roms() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("generated_roms");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("fibonacci.gb"), common::fibonacci()).unwrap();
+    fs::write(dir.join("mbc1_banks.gb"), common::mbc1_banks()).unwrap();
+    fs::write(dir.join("shades.gb"), common::shades()).unwrap();
+    image::write_png(
+        dir.join("shades.png"),
+        WIDTH,
+        HEIGHT,
+        &common::shades_picture(),
+    )
+    .unwrap();
+
+    let test = |rom: &str, condition, reference: Option<&str>| TestRom {
+        rom: dir.join(rom),
+        condition,
+        frames: 60,
+        reference: reference.map(|reference| dir.join(reference)),
+    };
+    let tests = [
+        test("fibonacci.gb", Condition::Mooneye, None),
+        test("mbc1_banks.gb", Condition::Mooneye, None),
+        test("shades.gb", Condition::Acid2, Some("shades.png")),
+    ];
+    run(&tests, &dir.join("output"));
+}